@@ -1,3 +1,4 @@
+from .asgi_server import Server, Settings, shutdown
 from .run import run
 
-__all__ = ["run"]
+__all__ = ["Server", "Settings", "run", "shutdown"]
@@ -8,17 +8,345 @@ from .run import run
     "-h", "--host", default="localhost", help="Host interface to listen for connections"
 )
 @click.option("-p", "--port", default=5000, type=int)
+@click.option(
+    "-b",
+    "--bind",
+    multiple=True,
+    metavar="ADDRESS",
+    help="Address to listen on instead of host and port, such as "
+    "'0.0.0.0:8000', '[::]:8000', 'unix:PATH' or 'fd://FD'. May be repeated.",
+)
+@click.option(
+    "--lifespan",
+    default="auto",
+    type=click.Choice(["auto", "on", "off"], case_sensitive=False),
+    help="Whether to run the ASGI lifespan protocol.",
+)
+@click.option("--certfile", help="PEM certificate file; enables HTTPS.")
+@click.option("--keyfile", help="PEM private key file for the certificate.")
+@click.option("--ca-certs", help="PEM bundle of intermediate CA certificates.")
+@click.option("--ciphers", help="Colon separated list of TLS cipher suites.")
+@click.option(
+    "--tls-min-version",
+    default="1.2",
+    type=click.Choice(["1.2", "1.3"]),
+    help="Oldest TLS version to accept.",
+)
+@click.option(
+    "--client-ca-certs", help="PEM bundle of CAs to verify client certificates."
+)
+@click.option(
+    "--client-auth",
+    default="none",
+    type=click.Choice(["none", "optional", "required"], case_sensitive=False),
+    help="Whether clients must present a TLS certificate.",
+)
+@click.option(
+    "--sni-cert",
+    "sni_certs",
+    multiple=True,
+    type=(str, str, str),
+    metavar="HOSTNAME CERTFILE KEYFILE",
+    help="Certificate to serve for a hostname, which may start with '*.'.",
+)
+@click.option(
+    "--tls-reload-interval",
+    default=10.0,
+    type=float,
+    help="Seconds between checks for changed certificates; 0 disables them.",
+)
+@click.option("--uds", help="Listen on this Unix domain socket instead of TCP.")
+@click.option(
+    "--uds-mode",
+    type=lambda mode: int(mode, 8),
+    help="Permissions for the Unix domain socket, in octal (e.g. 660).",
+)
+@click.option("--uds-owner", help="User to own the Unix domain socket.")
+@click.option("--uds-group", help="Group to own the Unix domain socket.")
+@click.option("--fd", type=int, help="Listen on an already bound socket.")
+@click.option(
+    "--socket-activation",
+    is_flag=True,
+    help="Listen on the socket passed by systemd socket activation.",
+)
+@click.option(
+    "--shutdown-timeout",
+    default=30.0,
+    type=float,
+    help="Seconds to wait for in-flight requests when shutting down.",
+)
+@click.option(
+    "--max-body-size",
+    type=int,
+    metavar="BYTES",
+    help="Answer requests with larger bodies with 413 Payload Too Large.",
+)
+@click.option(
+    "--header-read-timeout",
+    default=10.0,
+    type=float,
+    help="Seconds a client has to send a request's headers; 0 disables the limit.",
+)
+@click.option(
+    "--keep-alive-timeout",
+    default=5.0,
+    type=float,
+    help="Seconds to keep an idle connection open; 0 disables keep-alive.",
+)
+@click.option(
+    "--body-read-timeout",
+    type=float,
+    help="Answer requests whose body takes longer than this many seconds with 408.",
+)
+@click.option(
+    "--response-timeout",
+    type=float,
+    help="Cancel the app and answer 504 if it takes longer than this many seconds "
+    "to start a response.",
+)
+@click.option(
+    "--min-request-body-rate",
+    type=int,
+    metavar="BYTES",
+    help="Answer requests whose body arrives slower than this many bytes per second "
+    "with 408.",
+)
+@click.option(
+    "--min-response-rate",
+    type=int,
+    metavar="BYTES",
+    help="Close connections that take responses slower than this many bytes per second.",
+)
+@click.option(
+    "--min-rate-grace-period",
+    default=5.0,
+    type=float,
+    help="Seconds a client may fall behind the minimum rates by.",
+)
+@click.option(
+    "--max-connections",
+    type=int,
+    help="Connections each process keeps open at once; others wait to be accepted.",
+)
+@click.option(
+    "--max-concurrent-requests",
+    type=int,
+    help="Requests and WebSockets each process handles at once; others are answered "
+    "with 503.",
+)
+@click.option(
+    "--request-queue-timeout",
+    type=float,
+    help="Seconds a request over --max-concurrent-requests waits before 503.",
+)
+@click.option(
+    "--rate-limit",
+    type=float,
+    help="Requests per second each client IP may make; others are answered with 429.",
+)
+@click.option(
+    "--rate-limit-burst",
+    type=int,
+    help="Requests a client may make at once under --rate-limit.",
+)
+@click.option(
+    "--path-rate-limit",
+    "path_rate_limits",
+    multiple=True,
+    type=(str, float, int),
+    metavar="PREFIX RATE BURST",
+    help="Rate limit for paths under a prefix, instead of --rate-limit.",
+)
+@click.option(
+    "--forwarded-allow-ips",
+    default="",
+    metavar="ADDRESSES",
+    help="Comma separated proxy addresses or networks (or 'unix', or '*') whose "
+    "Forwarded and X-Forwarded-* headers are trusted.",
+)
+@click.option(
+    "--proxy-protocol",
+    is_flag=True,
+    help="Read the PROXY protocol header giving the client's address, as sent by "
+    "HAProxy or AWS NLB, from the start of every connection. Connections without "
+    "one are closed.",
+)
+@click.option(
+    "--root-path",
+    default="",
+    help="The path the app is mounted under behind a proxy, such as /svc/api. "
+    "Trusted proxies can give it with X-Forwarded-Prefix or X-Script-Name instead.",
+)
+@click.option(
+    "--root-path-included",
+    is_flag=True,
+    help="Request paths already start with the root path, because the proxy doesn't "
+    "strip it, so it isn't added to them again.",
+)
+@click.option(
+    "--workers",
+    type=int,
+    help="Serve from this many worker processes, which are restarted if they exit.",
+)
+@click.option(
+    "--max-requests",
+    type=int,
+    help="Replace a worker after it has served this many requests.",
+)
+@click.option(
+    "--max-requests-jitter",
+    default=0,
+    type=int,
+    help="Add up to this many requests to --max-requests at random for each worker.",
+)
+@click.option(
+    "--max-memory",
+    type=int,
+    metavar="MIB",
+    help="Replace a worker once its resident memory is over this many MiB.",
+)
+@click.option(
+    "--reload",
+    is_flag=True,
+    help="Restart the app when Python files change. For development only.",
+)
+@click.option(
+    "--reload-dir",
+    "reload_dirs",
+    multiple=True,
+    help="Directory to watch for changes instead of the working directory. "
+    "May be repeated.",
+)
+@click.option(
+    "--reload-include",
+    "reload_includes",
+    multiple=True,
+    metavar="GLOB",
+    help="Also watch files matching this pattern, such as '*.html'. May be repeated.",
+)
+@click.option(
+    "--reload-exclude",
+    "reload_excludes",
+    multiple=True,
+    metavar="GLOB",
+    help="Ignore files and directories matching this pattern. May be repeated.",
+)
 @click.option(
     "--log-level",
     default="INFO",
     type=click.Choice(["WARN", "DEBUG", "ERROR", "INFO"], case_sensitive=False),
     help="Control the verbosity of the server.",
 )
-def main(app, host, port, log_level):
+def main(
+    app,
+    host,
+    port,
+    bind,
+    lifespan,
+    certfile,
+    keyfile,
+    ca_certs,
+    ciphers,
+    tls_min_version,
+    client_ca_certs,
+    client_auth,
+    sni_certs,
+    tls_reload_interval,
+    uds,
+    uds_mode,
+    uds_owner,
+    uds_group,
+    fd,
+    socket_activation,
+    shutdown_timeout,
+    max_body_size,
+    header_read_timeout,
+    keep_alive_timeout,
+    body_read_timeout,
+    response_timeout,
+    min_request_body_rate,
+    min_response_rate,
+    min_rate_grace_period,
+    max_connections,
+    max_concurrent_requests,
+    request_queue_timeout,
+    rate_limit,
+    rate_limit_burst,
+    path_rate_limits,
+    forwarded_allow_ips,
+    proxy_protocol,
+    root_path,
+    root_path_included,
+    workers,
+    max_requests,
+    max_requests_jitter,
+    max_memory,
+    reload,
+    reload_dirs,
+    reload_includes,
+    reload_excludes,
+    log_level,
+):
     """Server an ASGI 3 callable
 
     APP argument should be in format: <pkg>.<module>:<app>"""
-    run(app, host=host, port=port, log_level=log_level.upper())
+    run(
+        app,
+        host=host,
+        port=port,
+        bind=list(bind),
+        lifespan=lifespan.lower(),
+        certfile=certfile,
+        keyfile=keyfile,
+        ca_certs=ca_certs,
+        ciphers=ciphers,
+        tls_min_version=tls_min_version,
+        client_ca_certs=client_ca_certs,
+        client_auth=client_auth.lower(),
+        sni_certs={
+            hostname: (sni_certfile, sni_keyfile)
+            for hostname, sni_certfile, sni_keyfile in sni_certs
+        },
+        tls_reload_interval=tls_reload_interval or None,
+        uds=uds,
+        uds_mode=uds_mode,
+        uds_owner=uds_owner,
+        uds_group=uds_group,
+        fd=fd,
+        socket_activation=socket_activation,
+        shutdown_timeout=shutdown_timeout,
+        max_body_size=max_body_size,
+        header_read_timeout=header_read_timeout or None,
+        keep_alive_timeout=keep_alive_timeout,
+        body_read_timeout=body_read_timeout,
+        response_timeout=response_timeout,
+        min_request_body_rate=min_request_body_rate,
+        min_response_rate=min_response_rate,
+        min_rate_grace_period=min_rate_grace_period,
+        max_connections=max_connections,
+        max_concurrent_requests=max_concurrent_requests,
+        request_queue_timeout=request_queue_timeout,
+        rate_limit=rate_limit,
+        rate_limit_burst=rate_limit_burst,
+        path_rate_limits={
+            prefix: (rate, burst) for prefix, rate, burst in path_rate_limits
+        },
+        forwarded_allow_ips=[
+            proxy.strip() for proxy in forwarded_allow_ips.split(",") if proxy.strip()
+        ],
+        proxy_protocol=proxy_protocol,
+        root_path=root_path,
+        root_path_included=root_path_included,
+        workers=workers,
+        max_requests=max_requests,
+        max_requests_jitter=max_requests_jitter,
+        max_memory=max_memory,
+        reload=reload,
+        reload_dirs=list(reload_dirs),
+        reload_includes=list(reload_includes),
+        reload_excludes=list(reload_excludes),
+        log_level=log_level.upper(),
+    )
 
 
 if __name__ == "__main__":
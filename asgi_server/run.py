@@ -1,8 +1,13 @@
 import asyncio
+import grp
 import importlib
+import json
 import logging
+import pwd
 import signal
-from typing import Awaitable, Callable, Union
+import socket
+import sys
+from typing import Awaitable, Callable, Dict, List, Optional, Tuple, Union
 
 from . import asgi_server
 
@@ -36,9 +41,60 @@ def run(
     app: Union[str, ASGI3Application],
     host="127.0.0.1",
     port=5000,
+    bind: Optional[List[str]] = None,
+    lifespan="auto",
+    certfile: Optional[str] = None,
+    keyfile: Optional[str] = None,
+    ca_certs: Optional[str] = None,
+    ciphers: Optional[str] = None,
+    tls_min_version="1.2",
+    client_ca_certs: Optional[str] = None,
+    client_auth="none",
+    sni_certs: Optional[Dict[str, Tuple[str, str]]] = None,
+    tls_reload_interval: Optional[float] = 10.0,
+    uds: Optional[str] = None,
+    uds_mode: Optional[int] = None,
+    uds_owner: Union[str, int, None] = None,
+    uds_group: Union[str, int, None] = None,
+    fd: Union[int, socket.socket, None] = None,
+    socket_activation=False,
+    shutdown_timeout: Optional[float] = 30.0,
+    max_body_size: Optional[int] = None,
+    header_read_timeout: Optional[float] = 10.0,
+    keep_alive_timeout: Optional[float] = 5.0,
+    body_read_timeout: Optional[float] = None,
+    response_timeout: Optional[float] = None,
+    min_request_body_rate: Optional[int] = None,
+    min_response_rate: Optional[int] = None,
+    min_rate_grace_period=5.0,
+    max_connections: Optional[int] = None,
+    max_concurrent_requests: Optional[int] = None,
+    request_queue_timeout: Optional[float] = None,
+    rate_limit: Optional[float] = None,
+    rate_limit_burst: Optional[int] = None,
+    path_rate_limits: Optional[Dict[str, Tuple[float, int]]] = None,
+    forwarded_allow_ips: Optional[List[str]] = None,
+    proxy_protocol=False,
+    root_path="",
+    root_path_included=False,
+    workers: Optional[int] = None,
+    max_requests: Optional[int] = None,
+    max_requests_jitter=0,
+    max_memory: Optional[int] = None,
+    supervisor_link_fd: Optional[int] = None,
+    reload=False,
+    reload_dirs: Optional[List[str]] = None,
+    reload_includes: Optional[List[str]] = None,
+    reload_excludes: Optional[List[str]] = None,
     log_level=logging.INFO,
 ) -> None:
-    if isinstance(app, str):
+    supervised = workers is not None or reload
+    if supervised and not isinstance(app, str):
+        raise ValueError(
+            "The app must be given as an import string, such as 'module:app', "
+            "to run it in worker processes or reload it."
+        )
+    if isinstance(app, str) and not supervised:
         app = import_app(app)
 
     init_logging(log_level)
@@ -46,17 +102,110 @@ def run(
     settings = asgi_server.Settings()
     settings.host = host
     settings.port = port
+    settings.bind = bind or []
+    settings.lifespan = lifespan
+    settings.certfile = certfile
+    settings.keyfile = keyfile
+    settings.ca_certs = ca_certs
+    settings.ciphers = ciphers
+    settings.tls_min_version = tls_min_version
+    settings.client_ca_certs = client_ca_certs
+    settings.client_auth = client_auth
+    settings.sni_certs = sni_certs or {}
+    settings.tls_reload_interval = tls_reload_interval
+    settings.uds = uds
+    settings.uds_mode = uds_mode
+    settings.uds_owner = (
+        pwd.getpwnam(uds_owner).pw_uid if isinstance(uds_owner, str) else uds_owner
+    )
+    settings.uds_group = (
+        grp.getgrnam(uds_group).gr_gid if isinstance(uds_group, str) else uds_group
+    )
+    settings.fd = fd
+    settings.socket_activation = socket_activation
+    settings.shutdown_timeout = shutdown_timeout
+    settings.max_body_size = max_body_size
+    settings.header_read_timeout = header_read_timeout
+    settings.keep_alive_timeout = keep_alive_timeout
+    settings.body_read_timeout = body_read_timeout
+    settings.response_timeout = response_timeout
+    settings.min_request_body_rate = min_request_body_rate
+    settings.min_response_rate = min_response_rate
+    settings.min_rate_grace_period = min_rate_grace_period
+    settings.max_connections = max_connections
+    settings.max_concurrent_requests = max_concurrent_requests
+    settings.request_queue_timeout = request_queue_timeout
+    settings.rate_limit = rate_limit
+    settings.rate_limit_burst = rate_limit_burst
+    settings.path_rate_limits = path_rate_limits or {}
+    settings.forwarded_allow_ips = forwarded_allow_ips or []
+    settings.proxy_protocol = proxy_protocol
+    settings.root_path = root_path
+    settings.root_path_included = root_path_included
+    settings.max_requests = max_requests
+    settings.max_requests_jitter = max_requests_jitter
+    settings.supervisor_link_fd = supervisor_link_fd
 
-    task = asgi_server.start_server(app, settings)
     loop = asyncio.get_event_loop()
 
-    def stop_server():
-        # fixme asgi lifecycle protocol
-        loop.stop()
-
-    loop.add_signal_handler(signal.SIGTERM, stop_server)
-
-    try:
-        loop.run_until_complete(task)
-    except KeyboardInterrupt:
-        stop_server()
+    # The server shuts itself down gracefully on SIGINT and SIGTERM, which
+    # needs the event loop to keep running the app until it is done. These
+    # handlers must be installed before the server starts, so that Python
+    # doesn't interrupt the loop when the signals arrive.
+    for sig in (signal.SIGINT, signal.SIGTERM):
+        loop.add_signal_handler(sig, lambda: None)
+
+    if not supervised:
+        task = asgi_server.start_server(app, settings)
+    else:
+        settings.workers = workers or 1
+        settings.max_memory = max_memory
+        settings.reload = reload
+        settings.reload_dirs = reload_dirs or []
+        settings.reload_includes = reload_includes or []
+        settings.reload_excludes = reload_excludes or []
+        # Workers serve on the sockets bound here, so they only need the
+        # rest of the settings.
+        worker_options = dict(
+            lifespan=lifespan,
+            certfile=certfile,
+            keyfile=keyfile,
+            ca_certs=ca_certs,
+            ciphers=ciphers,
+            tls_min_version=tls_min_version,
+            client_ca_certs=client_ca_certs,
+            client_auth=client_auth,
+            sni_certs=sni_certs,
+            tls_reload_interval=tls_reload_interval,
+            shutdown_timeout=shutdown_timeout,
+            max_body_size=max_body_size,
+            header_read_timeout=header_read_timeout,
+            keep_alive_timeout=keep_alive_timeout,
+            body_read_timeout=body_read_timeout,
+            response_timeout=response_timeout,
+            min_request_body_rate=min_request_body_rate,
+            min_response_rate=min_response_rate,
+            min_rate_grace_period=min_rate_grace_period,
+            max_connections=max_connections,
+            max_concurrent_requests=max_concurrent_requests,
+            request_queue_timeout=request_queue_timeout,
+            rate_limit=rate_limit,
+            rate_limit_burst=rate_limit_burst,
+            path_rate_limits=path_rate_limits,
+            forwarded_allow_ips=forwarded_allow_ips,
+            proxy_protocol=proxy_protocol,
+            root_path=root_path,
+            root_path_included=root_path_included,
+            max_requests=max_requests,
+            max_requests_jitter=max_requests_jitter,
+            log_level=log_level,
+        )
+        worker_command = [
+            sys.executable,
+            "-m",
+            "asgi_server.worker",
+            app,
+            json.dumps(worker_options),
+        ]
+        task = asgi_server.start_supervisor(settings, worker_command)
+    loop.run_until_complete(task)
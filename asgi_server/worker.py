@@ -0,0 +1,38 @@
+"""The entry point of the worker processes run by `start_supervisor`.
+
+Workers are passed the app's import string and the JSON encoded options for
+`run`, and inherit the listening sockets from the supervisor."""
+import json
+import os
+import sys
+
+from .run import run
+
+# The supervisor passes its listening sockets from this descriptor onwards.
+FIRST_LISTEN_FD = 3
+
+
+def main():
+    app, options = sys.argv[1], json.loads(sys.argv[2])
+    # Read before the server starts any threads, and kept from the app's
+    # subprocesses
+    listen_fds = int(os.environ.pop("ASGI_SERVER_LISTEN_FDS"))
+    supervisor_link_fd = int(os.environ.pop("ASGI_SERVER_LINK_FD"))
+    # JSON turns tuples into lists, which the settings don't accept.
+    for name in ("sni_certs", "path_rate_limits"):
+        if options.get(name):
+            options[name] = {key: tuple(value) for key, value in options[name].items()}
+
+    run(
+        app,
+        bind=[
+            f"fd://{fd}"
+            for fd in range(FIRST_LISTEN_FD, FIRST_LISTEN_FD + listen_fds)
+        ],
+        supervisor_link_fd=supervisor_link_fd,
+        **options,
+    )
+
+
+if __name__ == "__main__":
+    main()
@@ -1,3 +1,4 @@
+mod asgi_context;
 mod asgi_receive;
 mod asgi_send;
 
@@ -7,6 +8,7 @@ use pyo3::{
     types::*,
 };
 
+pub use asgi_context::AsgiContext;
 use asgi_receive::AsgiReceive;
 use asgi_send::AsgiSend;
 
@@ -19,6 +21,7 @@ use asgi_send::AsgiSend;
 #[derive(Clone)]
 pub struct AsgiDriver {
     asgi_app: PyObject,
+    state: Py<PyDict>,
 }
 
 impl AsgiDriver {
@@ -29,7 +32,15 @@ impl AsgiDriver {
     /// does not type check this. If this constraint fails, then an exception
     /// will be thrown into Python when `create_context` is used.
     pub fn new(asgi_app: PyObject) -> AsgiDriver {
-        AsgiDriver { asgi_app: asgi_app }
+        let state = Python::with_gil(|py| PyDict::new(py).into());
+        AsgiDriver { asgi_app, state }
+    }
+
+    /// The app's lifespan state: a dict which the app fills in while
+    /// handling `lifespan.startup`, and which every connection scope gets a
+    /// copy of.
+    pub fn state(&self) -> Py<PyDict> {
+        self.state.clone()
     }
 
     /// Start an ASGI 3.0 context, providing an ASGI scope, a stream of messages
@@ -41,22 +52,21 @@ impl AsgiDriver {
     /// (See the ASGI spec for more details).
     ///
     /// The return value is a future that wraps the completion of the Python
-    /// coroutine.
+    /// coroutine, and cancels it if dropped first.
     pub fn create_context(
         &self,
         scope: &PyDict,
         messages_to_py: mpsc::Receiver<Py<PyDict>>,
         results_from_py: mpsc::Sender<Py<PyDict>>,
-    ) -> PyResult<impl futures::Future<Output = PyResult<PyObject>> + Send> {
-        let result = Python::with_gil(|py| {
+    ) -> PyResult<AsgiContext> {
+        Python::with_gil(|py| {
             let receive = AsgiReceive::new(messages_to_py, py)?;
             let send = AsgiSend::new(results_from_py, py)?;
 
             AsgiDriver::ensure_asgi_field(scope, py)?;
             let coro = self.asgi_app.call1(py, (scope, receive, send))?;
-            pyo3_asyncio::into_future(coro.as_ref(py))
-        });
-        result
+            AsgiContext::spawn(py, coro.as_ref(py))
+        })
     }
 
     fn ensure_asgi_field(dict: &PyDict, py: Python) -> PyResult<()> {
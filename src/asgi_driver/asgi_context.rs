@@ -0,0 +1,93 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{channel::oneshot, FutureExt};
+use pyo3::{exceptions::*, prelude::*};
+
+/// A call into an ASGI app, running as a task on the Python event loop.
+///
+/// `AsgiContext` resolves with the result of the app's coroutine. If it is
+/// dropped before then, the task is cancelled, which raises
+/// `asyncio.CancelledError` inside the app.
+pub struct AsgiContext {
+    result: oneshot::Receiver<PyResult<PyObject>>,
+    /// The `concurrent.futures.Future` of the task, until it is done.
+    task: Option<PyObject>,
+}
+
+impl AsgiContext {
+    /// Schedule `awaitable` on the event loop that pyo3-asyncio runs.
+    pub fn spawn(py: Python, awaitable: &PyAny) -> PyResult<Self> {
+        let asyncio = py.import("asyncio")?;
+        // Only coroutines can be scheduled from another thread, so other
+        // awaitables are wrapped in one.
+        let coroutine = if asyncio.call1("iscoroutine", (awaitable,))?.is_true()? {
+            awaitable
+        } else {
+            asyncio.call1("wait_for", (awaitable, py.None()))?
+        };
+
+        let event_loop = pyo3_asyncio::get_event_loop(py);
+        let task = asyncio.call1("run_coroutine_threadsafe", (coroutine, event_loop))?;
+        let (sender, result) = oneshot::channel();
+        task.call_method1(
+            "add_done_callback",
+            (ContextDone {
+                sender: Some(sender),
+            },),
+        )?;
+
+        Ok(AsgiContext {
+            result,
+            task: Some(task.into()),
+        })
+    }
+}
+
+impl Future for AsgiContext {
+    type Output = PyResult<PyObject>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let context = self.get_mut();
+        let result = futures::ready!(context.result.poll_unpin(cx));
+        context.task = None;
+        Poll::Ready(
+            result.unwrap_or_else(|_| {
+                Err(PyRuntimeError::new_err("The ASGI app's task was dropped"))
+            }),
+        )
+    }
+}
+
+impl Drop for AsgiContext {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            Python::with_gil(|py| {
+                if let Err(py_err) = task.call_method0(py, "cancel") {
+                    log::warn!("Could not cancel the ASGI app: {}", py_err);
+                }
+            });
+        }
+    }
+}
+
+/// Passes the outcome of an app's task back to its `AsgiContext`.
+#[pyclass]
+struct ContextDone {
+    sender: Option<oneshot::Sender<PyResult<PyObject>>>,
+}
+
+#[pymethods]
+impl ContextDone {
+    #[call]
+    fn __call__(&mut self, task: &PyAny) {
+        let result = task.call_method0("result").map(PyObject::from);
+        if let Some(sender) = self.sender.take() {
+            // Nothing is waiting for the result if the context was dropped
+            sender.send(result).unwrap_or(());
+        }
+    }
+}
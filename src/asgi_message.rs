@@ -7,10 +7,19 @@ use pyo3::{
 pub trait AsgiMessage {
     fn message_type() -> &'static str;
 
-    fn validate_message_type(message_dict: &PyDict) -> PyResult<()> {
-        let message_type = PyAny::get_item(&message_dict, "type")?
+    fn get_message_type(message_dict: &PyDict) -> PyResult<&str> {
+        PyAny::get_item(message_dict, "type")?
             .downcast::<PyUnicode>()?
-            .to_str()?;
+            .to_str()
+    }
+
+    fn matches_message_type(message_dict: &PyDict) -> PyResult<bool> {
+        Self::get_message_type(message_dict)
+            .map(|message_type| message_type == Self::message_type())
+    }
+
+    fn validate_message_type(message_dict: &PyDict) -> PyResult<()> {
+        let message_type = Self::get_message_type(message_dict)?;
 
         if !message_type.eq(Self::message_type()) {
             Err(PyValueError::new_err(format!(
@@ -1,20 +1,28 @@
-use pyo3::{types::PyDict, PyResult, Python};
+use pyo3::{types::PyDict, Py, PyResult, Python};
 
 use super::scope_provider::ScopeProvider;
 
 pub enum Type {
-    HTTP,
+    Http,
+    WebSocket,
+    Lifespan,
 }
 
 impl ScopeProvider for Type {
     fn add_scope(&self, scope_dict: &PyDict) -> PyResult<()> {
         match self {
-            Self::HTTP => scope_dict.set_item("type", "http"),
+            Self::Http => scope_dict.set_item("type", "http"),
+            Self::WebSocket => scope_dict.set_item("type", "websocket"),
+            Self::Lifespan => scope_dict.set_item("type", "lifespan"),
         }
     }
 }
 
-pub struct AsgiVersion;
+/// The ASGI version, and the version of the protocol's spec that the scope
+/// and messages follow.
+pub struct AsgiVersion {
+    pub spec_version: &'static str,
+}
 impl ScopeProvider for AsgiVersion {
     fn add_scope(&self, scope_dict: &PyDict) -> PyResult<()> {
         Python::with_gil(|py| {
@@ -23,7 +31,39 @@ impl ScopeProvider for AsgiVersion {
             }
 
             let asgi_meta: &PyDict = scope_dict.get_item("asgi").unwrap().downcast()?;
-            asgi_meta.set_item("version", "3.0")
+            asgi_meta.set_item("version", "3.0")?;
+            asgi_meta.set_item("spec_version", self.spec_version)
+        })
+    }
+}
+
+/// The `extensions` dict, which the providers of each extension that a
+/// connection supports add to. It is there even if they all leave it
+/// empty, to tell the app that the server supports none.
+pub struct Extensions;
+impl ScopeProvider for Extensions {
+    fn add_scope(&self, scope_dict: &PyDict) -> PyResult<()> {
+        Python::with_gil(|py| {
+            if !scope_dict.contains("extensions")? {
+                scope_dict.set_item("extensions", PyDict::new(py))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+pub enum State {
+    /// The state dict itself, for the lifespan scope to fill in.
+    Lifespan(Py<PyDict>),
+    /// A shallow copy of the state dict, for connection scopes.
+    Connection(Py<PyDict>),
+}
+
+impl ScopeProvider for State {
+    fn add_scope(&self, scope_dict: &PyDict) -> PyResult<()> {
+        Python::with_gil(|py| match self {
+            Self::Lifespan(state) => scope_dict.set_item("state", state),
+            Self::Connection(state) => scope_dict.set_item("state", state.as_ref(py).copy()?),
         })
     }
 }
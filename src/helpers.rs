@@ -1,5 +1,16 @@
-use pyo3::{types::PyDict, Py, PyResult, Python};
+use pyo3::{
+    types::{IntoPyDict, PyDict},
+    Py, PyResult, Python,
+};
 
 pub trait TryIntoPyDict {
     fn try_into_py_dict(self, py: Python) -> PyResult<Py<PyDict>>;
 }
+
+/// Convert an outgoing ASGI event into a dict that can be handed to Python.
+pub fn into_py_message(message: impl IntoPyDict) -> Py<PyDict> {
+    Python::with_gil(|py| {
+        let message_dict: Py<PyDict> = message.into_py_dict(py).into();
+        message_dict
+    })
+}
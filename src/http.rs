@@ -1,12 +1,14 @@
+pub mod headers;
 mod request_message;
 mod response_body;
 mod response_head;
 mod response_message;
-mod scope;
+pub mod scope;
 
 use std::{
     convert::{Infallible, TryFrom},
-    net::SocketAddr,
+    sync::{Arc, OnceLock},
+    time::Duration,
 };
 
 use futures::{
@@ -15,7 +17,7 @@ use futures::{
     future::{self, AbortHandle},
     stream, Future, FutureExt, Stream, StreamExt,
 };
-use http::{response, Request, Response};
+use http::{header, response, Request, Response, StatusCode};
 use hyper::Body;
 use pyo3::{
     exceptions::PyValueError,
@@ -23,18 +25,42 @@ use pyo3::{
     Py, PyErr, PyResult, Python,
 };
 
+pub use self::response_body::HttpResponseBody;
 use self::{
-    request_message::HttpRequestMessage, response_body::HttpResponseBody,
+    request_message::{BodyError, HttpRequestMessage},
     response_head::HttpResponseStart,
 };
-use crate::{asgi_driver::AsgiDriver, error};
+use crate::{
+    asgi_driver::AsgiDriver,
+    error,
+    server::{ConnectionInfo, MinDataRate},
+};
 use crate::{asgi_scope, helpers::TryIntoPyDict};
 
+/// Limits on the HTTP requests that are passed to the app.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpConfig {
+    /// The largest request body, in bytes, that the app is given. Larger
+    /// requests are answered with 413 Payload Too Large.
+    pub max_body_size: Option<u64>,
+    /// How long a client has to send a request's whole body, counted from
+    /// when its headers have been read. Time that the app takes to ask for
+    /// more of the body isn't counted. Slower requests are answered with
+    /// 408 Request Timeout.
+    pub body_read_timeout: Option<Duration>,
+    /// How long the app has to start its response. Slower apps are
+    /// cancelled, and the request is answered with 504 Gateway Timeout.
+    pub response_timeout: Option<Duration>,
+    /// How quickly clients have to send request bodies. Slower requests are
+    /// answered with 408 Request Timeout.
+    pub min_request_body_rate: Option<MinDataRate>,
+}
+
 pub async fn handle_request(
-    server_addr: SocketAddr,
-    remote_addr: SocketAddr,
+    connection: ConnectionInfo,
     request: Request<Body>,
     asgi_driver: AsgiDriver,
+    config: HttpConfig,
 ) -> Result<Response<HttpResponseBody>, Infallible> {
     let (parts, request_body) = request.into_parts();
     let req_method = parts.method.to_string();
@@ -45,9 +71,31 @@ pub async fn handle_request(
         .unwrap_or("/")
         .to_string();
     let req_ver = version_as_string(&parts.version);
+    let log_response = |status_code: u16| {
+        log::info!(
+            "{} - \"{} {} {}\" {}",
+            connection.remote_addr(),
+            req_method,
+            req_path,
+            req_ver,
+            status_code
+        )
+    };
+
+    if let Some(max_body_size) = config.max_body_size {
+        if content_length(&parts.headers).is_some_and(|length| length > max_body_size) {
+            log_response(StatusCode::PAYLOAD_TOO_LARGE.as_u16());
+            return Ok(response::Response::builder()
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .body(HttpResponseBody::ignoring(request_body))
+                .unwrap());
+        }
+    }
 
+    let body_error = Arc::new(OnceLock::new());
     let (disconnect_emitter, disconnect_event) = oneshot::channel::<()>();
-    let stream_to_py = get_messages_to_py_stream(request_body, disconnect_event);
+    let stream_to_py =
+        get_messages_to_py_stream(request_body, &config, body_error.clone(), disconnect_event);
 
     // These channels will be used to communicate between python and rust
     let (results_from_py, mut messages_to_rust) = mpsc::channel::<Py<pyo3::types::PyDict>>(1);
@@ -56,39 +104,57 @@ pub async fn handle_request(
     let rt = pyo3_asyncio::tokio::get_runtime();
 
     rt.spawn(stream_to_py.forward(results_from_rust));
-    rt.spawn(call_asgi_app(
-        scope::build(parts, remote_addr.clone(), server_addr),
+    let (app_call, app_abort_handle) = future::abortable(call_asgi_app(
+        scope::build(parts, &connection, asgi_driver.state()),
         asgi_driver,
         messages_to_py,
         results_from_py,
     ));
+    rt.spawn(app_call);
 
-    let response_head = messages_to_rust
-        .next()
-        .await
+    let response_start = match config.response_timeout {
+        Some(response_timeout) => {
+            match tokio::time::timeout(response_timeout, messages_to_rust.next()).await {
+                Ok(message) => message,
+                Err(_) => {
+                    log::warn!(
+                        "The app did not start a response within {:?}; cancelling it",
+                        response_timeout
+                    );
+                    // Dropping the app's context cancels its Python task
+                    app_abort_handle.abort();
+                    log_response(StatusCode::GATEWAY_TIMEOUT.as_u16());
+                    return Ok(error_response(StatusCode::GATEWAY_TIMEOUT));
+                }
+            }
+        }
+        None => messages_to_rust.next().await,
+    };
+    let response_head = response_start
         .ok_or_else(|| PyValueError::new_err("No response start message received"))
-        .and_then(|message_dict| HttpResponseStart::try_from(message_dict));
+        .and_then(HttpResponseStart::try_from);
 
     if response_head.is_ok() {
         disconnect_emitter.send(()).unwrap_or(());
+    } else if let Some(body_error) = body_error.get() {
+        // The app stopped at the disconnect it was sent, without answering
+        let status = match body_error {
+            BodyError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            BodyError::TimedOut | BodyError::TooSlow => StatusCode::REQUEST_TIMEOUT,
+        };
+        log_response(status.as_u16());
+        return Ok(error_response(status));
     }
 
     response_head
         .and_then(|head| {
-            log::info!(
-                "{} - \"{} {} {}\" {}",
-                remote_addr,
-                req_method,
-                req_path,
-                req_ver,
-                head.status_code()
-            );
+            log_response(head.status_code());
             build_response(head, messages_to_rust)
         })
         .or_else(handle_error)
 }
 
-fn call_asgi_app(
+pub fn call_asgi_app(
     scope_provider: impl asgi_scope::ScopeProvider,
     asgi_driver: AsgiDriver,
     messages_to_py: mpsc::Receiver<Py<PyDict>>,
@@ -100,11 +166,7 @@ fn call_asgi_app(
             .and_then(move |scope_dict| {
                 asgi_driver.create_context(scope_dict.as_ref(py), messages_to_py, results_from_py)
             })
-            .map(|asgi_context| {
-                asgi_context.then(|result| match result {
-                    _ => future::ready(()),
-                })
-            })
+            .map(|asgi_context| asgi_context.then(|_| future::ready(())))
             .map(future::Either::Left)
             .map_err(error::ApplicationError::from)
             .or_else(|err| err.handle(future::Either::Right(future::ready(()))))
@@ -114,22 +176,52 @@ fn call_asgi_app(
 
 fn get_messages_to_py_stream(
     body: Body,
+    config: &HttpConfig,
+    body_error: Arc<OnceLock<BodyError>>,
     disconnect_event: oneshot::Receiver<()>,
 ) -> impl Stream<Item = Result<Py<PyDict>, mpsc::SendError>> {
-    let (message_stream, message_stream_abort_handle) = stream::abortable(get_message_stream(body));
+    let (message_stream, message_stream_abort_handle) =
+        stream::abortable(get_message_stream(body, config, body_error));
     message_stream
         .chain(get_disconnect_stream(
             disconnect_event,
             message_stream_abort_handle,
         ))
-        .map(|message_dict| Ok(message_dict))
+        .map(Ok)
 }
 
-fn get_message_stream(body: Body) -> impl Stream<Item = Py<PyDict>> {
-    HttpRequestMessage::stream_body(body).map(|message| {
-        Python::with_gil(|py| {
-            let message_dict: Py<PyDict> = message.into_py_dict(py).into();
-            message_dict
+fn get_message_stream(
+    body: Body,
+    config: &HttpConfig,
+    body_error: Arc<OnceLock<BodyError>>,
+) -> impl Stream<Item = Py<PyDict>> {
+    let config = config.clone();
+    HttpRequestMessage::stream_body(body, &config).map(move |message| {
+        Python::with_gil(|py| match message {
+            Ok(message) => {
+                let message_dict: Py<PyDict> = message.into_py_dict(py).into();
+                message_dict
+            }
+            Err(err) => {
+                match err {
+                    BodyError::TooLarge => log::warn!(
+                        "Request body is larger than the limit of {} bytes; disconnecting",
+                        config.max_body_size.unwrap_or_default()
+                    ),
+                    BodyError::TimedOut => log::warn!(
+                        "Request body was not received within {:?}; disconnecting",
+                        config.body_read_timeout.unwrap_or_default()
+                    ),
+                    BodyError::TooSlow => log::warn!(
+                        "Request body is arriving slower than {} bytes per second; disconnecting",
+                        config
+                            .min_request_body_rate
+                            .map_or(0, |rate| rate.bytes_per_second)
+                    ),
+                }
+                body_error.set(err).unwrap_or(());
+                disconnect_message(py)
+            }
         })
     })
 }
@@ -144,32 +236,63 @@ fn get_disconnect_stream(
         // don't send any more request messages into Python
         message_stream_abort_handle.abort();
 
-        Python::with_gil(|py| {
-            let message_dict = PyDict::new(py);
-            message_dict
-                .set_item("type", "http.disconnect")
-                .unwrap_or(());
-            let message_dict: Py<PyDict> = message_dict.into_py_dict(py).into();
-            message_dict
-        })
+        Python::with_gil(disconnect_message)
     })
 }
 
+fn disconnect_message(py: Python) -> Py<PyDict> {
+    let message_dict = PyDict::new(py);
+    message_dict
+        .set_item("type", "http.disconnect")
+        .unwrap_or(());
+    message_dict.into()
+}
+
+/// The request's `Content-Length`, if it gave a valid one.
+fn content_length(headers: &http::HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// An empty response sent by the server itself, rather than the app.
+fn error_response(status: StatusCode) -> Response<HttpResponseBody> {
+    response::Response::builder()
+        .status(status)
+        .body(HttpResponseBody::new())
+        .unwrap()
+}
+
+/// The response to a request that the server turns away before it reaches
+/// the app, such as when it is too busy. It is sent without reading the
+/// request body or taking the GIL.
+pub fn retry_later(
+    request: Request<Body>,
+    status: StatusCode,
+    retry_after_seconds: u64,
+) -> Response<HttpResponseBody> {
+    response::Response::builder()
+        .status(status)
+        .header(header::RETRY_AFTER, retry_after_seconds)
+        .body(HttpResponseBody::ignoring(request.into_body()))
+        .unwrap()
+}
+
 fn build_response(
     response_head: HttpResponseStart,
     messages_to_rust: mpsc::Receiver<Py<PyDict>>,
 ) -> PyResult<Response<HttpResponseBody>> {
     let builder = response::Builder::from(response_head);
     let body = HttpResponseBody::from(messages_to_rust);
-    builder.body(body).map_err(|err| {
-        PyValueError::new_err(format!(
-            "Could not start sending request: {}",
-            err.to_string()
-        ))
-    })
+    builder
+        .body(body)
+        .map_err(|err| PyValueError::new_err(format!("Could not start sending request: {}", err)))
 }
 
-fn handle_error(err: PyErr) -> Result<Response<HttpResponseBody>, Infallible> {
+pub fn handle_error(err: PyErr) -> Result<Response<HttpResponseBody>, Infallible> {
     let err = error::ApplicationError::from(err);
     let default_response = response::Response::builder()
         .status(500)
@@ -178,14 +301,72 @@ fn handle_error(err: PyErr) -> Result<Response<HttpResponseBody>, Infallible> {
     err.handle(default_response)
 }
 
-fn version_as_string(version: &http::Version) -> String {
-    let version_name = match version {
-        &http::Version::HTTP_09 => "0.9",
-        &http::Version::HTTP_10 => "1.0",
-        &http::Version::HTTP_11 => "1.1",
-        &http::Version::HTTP_2 => "2",
-        &http::Version::HTTP_3 => "3",
+pub fn version_as_string(version: &http::Version) -> String {
+    let version_name = match *version {
+        http::Version::HTTP_09 => "0.9",
+        http::Version::HTTP_10 => "1.0",
+        http::Version::HTTP_11 => "1.1",
+        http::Version::HTTP_2 => "2",
+        http::Version::HTTP_3 => "3",
         _ => "?",
     };
     format!("HTTP/{}", version_name)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use http::HeaderMap;
+
+    use super::*;
+    use crate::server::Address;
+
+    fn headers(content_length: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(content_length) = content_length {
+            headers.insert(header::CONTENT_LENGTH, content_length.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn reads_a_valid_content_length() {
+        assert_eq!(content_length(&headers(Some("0"))), Some(0));
+        assert_eq!(content_length(&headers(Some("1048576"))), Some(1048576));
+    }
+
+    #[test]
+    fn missing_or_invalid_content_length_is_none() {
+        assert_eq!(content_length(&headers(None)), None);
+        for invalid in &["", "-1", "1.5", "ten", "1, 2", "99999999999999999999"] {
+            assert_eq!(
+                content_length(&headers(Some(invalid))),
+                None,
+                "for {:?}",
+                invalid
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn declared_body_over_the_limit_is_refused_before_the_app_runs() {
+        let request = Request::post("/upload")
+            .header(header::CONTENT_LENGTH, "11")
+            .body(Body::from("hello world"))
+            .unwrap();
+        let address = Address::Inet("127.0.0.1:8000".parse::<SocketAddr>().unwrap());
+        let connection = ConnectionInfo::new(address.clone(), address, None);
+        // The app would fail if it were called
+        let driver = AsgiDriver::new(Python::with_gil(|py| py.None()));
+        let config = HttpConfig {
+            max_body_size: Some(10),
+            ..HttpConfig::default()
+        };
+
+        let response = handle_request(connection, request, driver, config)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -4,39 +4,96 @@ use pyo3::{
     types::{IntoPyDict, PyBytes, PyDict},
     Python,
 };
+use tokio::time::Instant;
 
-use crate::error;
+use super::HttpConfig;
+use crate::{error, server::DataRateMonitor};
 
 pub struct HttpRequestMessage {
     data: Bytes,
     more: bool,
 }
 
+/// Why the server stopped passing a request's body to the app.
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body grew past the configured limit.
+    TooLarge,
+    /// The client took too long to send the body.
+    TimedOut,
+    /// The client sent the body slower than the minimum rate.
+    TooSlow,
+}
+
 impl HttpRequestMessage {
-    pub fn stream_body(body: Body) -> impl Stream<Item = HttpRequestMessage> {
-        body.map(|chunk| {
-            chunk
-                .map(|data| HttpRequestMessage { data, more: true })
-                .map_err(|hyper_err| error::ServerError::from(hyper_err))
-                .or_else(|err| {
-                    err.handle(HttpRequestMessage {
-                        data: Bytes::new(),
-                        more: false,
-                    })
-                })
-                .unwrap()
-        })
-        .chain(stream::once(async {
-            HttpRequestMessage {
+    /// Stream the request body as messages, which end with a `BodyError`
+    /// once the body breaks one of the limits in `config`.
+    pub fn stream_body(
+        body: Body,
+        config: &HttpConfig,
+    ) -> impl Stream<Item = Result<HttpRequestMessage, BodyError>> {
+        let max_body_size = config.max_body_size;
+        let rate_monitor = config.min_request_body_rate.map(DataRateMonitor::new);
+
+        // Like the rate, the read timeout only counts the time spent waiting
+        // for the client, and not time that the app takes to ask for more
+        let state = (body, 0u64, config.body_read_timeout, rate_monitor);
+        stream::unfold(Some(state), move |state| async move {
+            let (mut body, received, read_time_left, mut rate_monitor) = state?;
+            let waiting_since = Instant::now();
+            let read_deadline = read_time_left.map(|time_left| waiting_since + time_left);
+            let rate_deadline = rate_monitor
+                .as_ref()
+                .map(|rate_monitor| waiting_since + rate_monitor.remaining());
+            let deadline = match (read_deadline, rate_deadline) {
+                (Some(read), Some(rate)) if rate < read => Some((rate, BodyError::TooSlow)),
+                (Some(read), _) => Some((read, BodyError::TimedOut)),
+                (None, rate) => rate.map(|rate| (rate, BodyError::TooSlow)),
+            };
+            let chunk = match deadline {
+                Some((deadline, err)) => {
+                    match tokio::time::timeout_at(deadline, body.next()).await {
+                        Ok(chunk) => chunk,
+                        Err(_) => return Some((Err(err), None)),
+                    }
+                }
+                None => body.next().await,
+            };
+
+            let last_message = HttpRequestMessage {
                 data: Bytes::new(),
                 more: false,
+            };
+            let message = match chunk {
+                Some(Ok(data)) => HttpRequestMessage { data, more: true },
+                Some(Err(hyper_err)) => error::ServerError::from(hyper_err)
+                    .handle(last_message)
+                    .unwrap(),
+                None => last_message,
+            };
+
+            let length = message.data.len() as u64;
+            let waited = waiting_since.elapsed();
+            if let Some(rate_monitor) = &mut rate_monitor {
+                rate_monitor.record(waited, length);
             }
-        }))
+            let read_time_left = read_time_left.map(|time_left| time_left.saturating_sub(waited));
+            let received = received + length;
+            if max_body_size.is_some_and(|max_body_size| received > max_body_size) {
+                return Some((Err(BodyError::TooLarge), None));
+            }
+            let state = if message.more {
+                Some((body, received, read_time_left, rate_monitor))
+            } else {
+                None
+            };
+            Some((Ok(message), state))
+        })
     }
 }
 
 impl IntoPyDict for HttpRequestMessage {
-    fn into_py_dict(self, py: Python) -> &PyDict {
+    fn into_py_dict(self, py: Python<'_>) -> &PyDict {
         let data: &[u8] = &self.data;
         let data = PyBytes::new(py, data);
         let dict = PyDict::new(py);
@@ -48,3 +105,101 @@ impl IntoPyDict for HttpRequestMessage {
         dict
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+    const CHUNK: &[u8] = b"chunk";
+
+    fn timeout_config() -> HttpConfig {
+        HttpConfig {
+            body_read_timeout: Some(TIMEOUT),
+            ..HttpConfig::default()
+        }
+    }
+
+    fn size_config(max_body_size: u64) -> HttpConfig {
+        HttpConfig {
+            max_body_size: Some(max_body_size),
+            ..HttpConfig::default()
+        }
+    }
+
+    /// Send a chunk of a body after each delay, then end it.
+    fn send_body(mut sender: hyper::body::Sender, delays: Vec<Duration>) {
+        tokio::spawn(async move {
+            for delay in delays {
+                tokio::time::sleep(delay).await;
+                sender.send_data(Bytes::from_static(CHUNK)).await.unwrap();
+            }
+        });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn time_the_app_takes_to_receive_is_not_counted() {
+        let (sender, body) = Body::channel();
+        send_body(sender, vec![Duration::ZERO, TIMEOUT * 7 / 4]);
+        let mut messages = Box::pin(HttpRequestMessage::stream_body(body, &timeout_config()));
+        assert!(messages.next().await.unwrap().unwrap().more);
+
+        // The app is busy for longer than the timeout, and then has to wait
+        // a little for the rest of the body
+        tokio::time::sleep(TIMEOUT * 3 / 2).await;
+        let message = messages.next().await.unwrap().unwrap();
+        assert_eq!(message.data, Bytes::from_static(CHUNK));
+        assert!(!messages.next().await.unwrap().unwrap().more);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn time_waiting_for_the_client_adds_up() {
+        let (sender, body) = Body::channel();
+        send_body(
+            sender,
+            vec![TIMEOUT * 2 / 5, TIMEOUT * 2 / 5, TIMEOUT * 2 / 5],
+        );
+        let mut messages = Box::pin(HttpRequestMessage::stream_body(body, &timeout_config()));
+
+        assert!(messages.next().await.unwrap().unwrap().more);
+        assert!(messages.next().await.unwrap().unwrap().more);
+        assert!(matches!(
+            messages.next().await,
+            Some(Err(BodyError::TimedOut))
+        ));
+        assert!(messages.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn body_larger_than_the_limit_is_too_large() {
+        let (sender, body) = Body::channel();
+        send_body(sender, vec![Duration::ZERO; 3]);
+        let config = size_config(2 * CHUNK.len() as u64);
+        let mut messages = Box::pin(HttpRequestMessage::stream_body(body, &config));
+
+        assert!(messages.next().await.unwrap().unwrap().more);
+        assert!(messages.next().await.unwrap().unwrap().more);
+        assert!(matches!(
+            messages.next().await,
+            Some(Err(BodyError::TooLarge))
+        ));
+        assert!(messages.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn body_at_the_limit_is_passed_on() {
+        let (sender, body) = Body::channel();
+        send_body(sender, vec![Duration::ZERO; 2]);
+        let config = size_config(2 * CHUNK.len() as u64);
+        let mut messages = Box::pin(HttpRequestMessage::stream_body(body, &config));
+
+        assert!(messages.next().await.unwrap().unwrap().more);
+        assert!(messages.next().await.unwrap().unwrap().more);
+        let last_message = messages.next().await.unwrap().unwrap();
+        assert!(!last_message.more);
+        assert!(last_message.data.is_empty());
+        assert!(messages.next().await.is_none());
+    }
+}
@@ -1,19 +1,35 @@
 use std::{convert::TryFrom, pin::Pin, task::Poll};
 
 use futures::{channel::mpsc, FutureExt, StreamExt};
-use hyper::body::{Bytes, HttpBody};
+use hyper::{
+    body::{Bytes, HttpBody},
+    Body,
+};
 use pyo3::{types::PyDict, Py, PyErr, Python};
 
 use super::response_message::HttpResponseMessage;
 
 pub struct HttpResponseBody {
     message_stream: Option<mpsc::Receiver<Py<PyDict>>>,
+    /// A request body that was left unread, which is kept until the response
+    /// has been sent. HTTP/2 clients are then told that they can stop
+    /// sending it, rather than that their request was cancelled.
+    _unread_request_body: Option<Body>,
 }
 
 impl HttpResponseBody {
     pub fn new() -> Self {
         Self {
             message_stream: None,
+            _unread_request_body: None,
+        }
+    }
+
+    /// An empty body for a response sent without reading the request body.
+    pub fn ignoring(request_body: Body) -> Self {
+        Self {
+            message_stream: None,
+            _unread_request_body: Some(request_body),
         }
     }
 }
@@ -22,6 +38,7 @@ impl From<mpsc::Receiver<Py<PyDict>>> for HttpResponseBody {
     fn from(message_stream: mpsc::Receiver<Py<PyDict>>) -> Self {
         Self {
             message_stream: Some(message_stream),
+            _unread_request_body: None,
         }
     }
 }
@@ -15,7 +15,7 @@ pub struct HttpResponseMessage {
 
 impl HttpResponseMessage {
     fn get_bytes(message_dict: &PyDict) -> PyResult<Bytes> {
-        let py_bytes = PyAny::get_item(&message_dict, "body")?
+        let py_bytes = PyAny::get_item(message_dict, "body")?
             .downcast::<PyBytes>()
             .map_err(PyErr::from)?;
 
@@ -23,11 +23,11 @@ impl HttpResponseMessage {
     }
 
     fn get_is_more_data(message_dict: &PyDict) -> PyResult<bool> {
-        PyAny::get_item(&message_dict, "more_body")?.extract::<bool>()
+        PyAny::get_item(message_dict, "more_body")?.extract::<bool>()
     }
 
     pub fn is_last_message(&self) -> bool {
-        return !self.more;
+        !self.more
     }
 }
 
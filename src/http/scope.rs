@@ -1,28 +1,360 @@
-mod providers;
+pub mod providers;
 mod scope_builder;
 mod scope_provider;
 
-use std::net::SocketAddr;
+use pyo3::{types::PyDict, Py};
 
-use crate::asgi_scope;
-use scope_builder::HttpScopeBuilder;
+use crate::{asgi_scope, server::ConnectionInfo};
+pub use scope_builder::HttpScopeBuilder;
+pub use scope_provider::HttpScopeProvider;
 
 pub fn build(
     parts: http::request::Parts,
-    client_addr: SocketAddr,
-    server_addr: SocketAddr,
+    connection: &ConnectionInfo,
+    state: Py<PyDict>,
 ) -> impl asgi_scope::ScopeProvider {
     asgi_scope::ScopeBuilder::new()
-        .add_provider(asgi_scope::providers::Type::HTTP)
-        .add_provider(providers::HttpAddress::ClientSocket(client_addr))
-        .add_provider(providers::HttpAddress::ServerSocket(server_addr))
+        .add_provider(asgi_scope::providers::Type::Http)
+        .add_provider(asgi_scope::providers::AsgiVersion {
+            spec_version: "2.3",
+        })
+        .add_provider(providers::HttpAddress::ClientSocket(
+            connection.remote_addr().clone(),
+        ))
+        .add_provider(providers::HttpAddress::server(connection))
+        .add_provider(asgi_scope::providers::Extensions)
+        .add_provider(providers::TlsExtension(connection.tls()))
+        .add_provider(providers::ProxyProtocolExtension(connection.proxy_header()))
+        .add_provider(asgi_scope::providers::State::Connection(state))
         .add_provider(
             HttpScopeBuilder::new(parts)
                 .add_provider(providers::HttpVersion {})
-                .add_provider(providers::HttpScheme {})
+                .add_provider(providers::HttpScheme {
+                    secure: connection.is_secure(),
+                })
                 .add_provider(providers::HttpMethod {})
-                .add_provider(providers::HttpPath {})
+                .add_provider(providers::HttpPath {
+                    root_path: connection.root_path().to_string(),
+                    root_path_included: connection.root_path_included(),
+                })
                 .add_provider(providers::HttpQueryString {})
                 .add_provider(providers::HttpHeaders {}),
         )
 }
+
+/// Checks the scope against the ASGI HTTP connection scope spec, field by
+/// field: https://asgi.readthedocs.io/en/latest/specs/www.html#http-connection-scope
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, net::SocketAddr, path::PathBuf};
+
+    use http::{Method, Request, Version};
+    use pyo3::{
+        types::{PyBytes, PyDict, PyList, PyTuple},
+        PyAny, Python,
+    };
+
+    use super::*;
+    use crate::{
+        helpers::TryIntoPyDict,
+        server::{Address, Forwarded},
+    };
+
+    /// Every key that the spec defines for an HTTP scope.
+    const SPEC_KEYS: &[&str] = &[
+        "type",
+        "asgi",
+        "http_version",
+        "method",
+        "scheme",
+        "path",
+        "raw_path",
+        "query_string",
+        "root_path",
+        "headers",
+        "client",
+        "server",
+        "state",
+        "extensions",
+    ];
+
+    fn inet(addr: &str) -> Address {
+        Address::Inet(addr.parse::<SocketAddr>().unwrap())
+    }
+
+    fn tcp_connection() -> ConnectionInfo {
+        ConnectionInfo::new(inet("192.0.2.7:5678"), inet("127.0.0.1:8000"), None)
+    }
+
+    fn mounted_connection(root_path: &str, root_path_included: bool) -> ConnectionInfo {
+        tcp_connection().forwarded(Forwarded {
+            root_path: Some(root_path.to_string()),
+            root_path_included,
+            ..Forwarded::default()
+        })
+    }
+
+    fn build_scope<'py>(
+        py: Python<'py>,
+        request: Request<()>,
+        connection: &ConnectionInfo,
+    ) -> &'py PyDict {
+        let (parts, _) = request.into_parts();
+        build(parts, connection, PyDict::new(py).into())
+            .try_into_py_dict(py)
+            .unwrap()
+            .into_ref(py)
+    }
+
+    fn get<'py>(scope: &'py PyDict, key: &str) -> &'py PyAny {
+        scope
+            .get_item(key)
+            .unwrap_or_else(|| panic!("{} is missing", key))
+    }
+
+    fn string(scope: &PyDict, key: &str) -> String {
+        get(scope, key).extract().unwrap()
+    }
+
+    fn bytes(scope: &PyDict, key: &str) -> Vec<u8> {
+        get(scope, key)
+            .downcast::<PyBytes>()
+            .unwrap_or_else(|_| panic!("{} is not bytes", key))
+            .as_bytes()
+            .to_vec()
+    }
+
+    fn headers(scope: &PyDict) -> Vec<(Vec<u8>, Vec<u8>)> {
+        get(scope, "headers")
+            .downcast::<PyList>()
+            .unwrap()
+            .iter()
+            .map(|header| {
+                let header = header.downcast::<PyTuple>().unwrap();
+                assert_eq!(header.len(), 2);
+                let name = header.get_item(0).downcast::<PyBytes>().unwrap();
+                let value = header.get_item(1).downcast::<PyBytes>().unwrap();
+                (name.as_bytes().to_vec(), value.as_bytes().to_vec())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn has_every_spec_field_with_its_type() {
+        Python::with_gil(|py| {
+            let request = Request::get("/caf%C3%A9/a%20b?x=1&y=%20z+w")
+                .header("Host", "example.com")
+                .header("X-Custom", "one")
+                .body(())
+                .unwrap();
+            let scope = build_scope(py, request, &tcp_connection());
+
+            let keys: BTreeSet<String> = scope
+                .keys()
+                .iter()
+                .map(|key| key.extract().unwrap())
+                .collect();
+            let spec_keys: BTreeSet<String> = SPEC_KEYS.iter().map(|key| key.to_string()).collect();
+            assert_eq!(keys, spec_keys);
+
+            assert_eq!(string(scope, "type"), "http");
+            let asgi = get(scope, "asgi").downcast::<PyDict>().unwrap();
+            assert_eq!(string(asgi, "version"), "3.0");
+            assert_eq!(string(asgi, "spec_version"), "2.3");
+            assert_eq!(string(scope, "http_version"), "1.1");
+            assert_eq!(string(scope, "method"), "GET");
+            assert_eq!(string(scope, "scheme"), "http");
+            assert_eq!(string(scope, "path"), "/café/a b");
+            assert_eq!(bytes(scope, "raw_path"), b"/caf%C3%A9/a%20b");
+            assert_eq!(bytes(scope, "query_string"), b"x=1&y=%20z+w");
+            assert_eq!(string(scope, "root_path"), "");
+            assert_eq!(
+                headers(scope),
+                vec![
+                    (b"host".to_vec(), b"example.com".to_vec()),
+                    (b"x-custom".to_vec(), b"one".to_vec()),
+                ]
+            );
+            let client: (String, u16) = get(scope, "client").extract().unwrap();
+            assert_eq!(client, ("192.0.2.7".to_string(), 5678));
+            let server: (String, u16) = get(scope, "server").extract().unwrap();
+            assert_eq!(server, ("127.0.0.1".to_string(), 8000));
+            assert!(get(scope, "state").downcast::<PyDict>().is_ok());
+            let extensions = get(scope, "extensions").downcast::<PyDict>().unwrap();
+            assert_eq!(extensions.len(), 0);
+        })
+    }
+
+    #[test]
+    fn query_string_is_empty_bytes_when_missing_or_empty() {
+        Python::with_gil(|py| {
+            for uri in &["/plain", "/plain?"] {
+                let request = Request::get(*uri).body(()).unwrap();
+                let scope = build_scope(py, request, &tcp_connection());
+                assert_eq!(bytes(scope, "query_string"), b"", "for {}", uri);
+                assert_eq!(string(scope, "path"), "/plain");
+            }
+        })
+    }
+
+    #[test]
+    fn method_and_http_version_follow_the_request() {
+        Python::with_gil(|py| {
+            let request = Request::builder()
+                .method(Method::from_bytes(b"PURGE").unwrap())
+                .version(Version::HTTP_10)
+                .uri("/")
+                .body(())
+                .unwrap();
+            let scope = build_scope(py, request, &tcp_connection());
+            assert_eq!(string(scope, "method"), "PURGE");
+            assert_eq!(string(scope, "http_version"), "1.0");
+        })
+    }
+
+    #[test]
+    fn asterisk_form_is_passed_through() {
+        Python::with_gil(|py| {
+            let request = Request::builder()
+                .method(Method::OPTIONS)
+                .uri("*")
+                .body(())
+                .unwrap();
+            let scope = build_scope(py, request, &tcp_connection());
+            assert_eq!(string(scope, "method"), "OPTIONS");
+            assert_eq!(string(scope, "path"), "*");
+            assert_eq!(bytes(scope, "raw_path"), b"*");
+            assert_eq!(bytes(scope, "query_string"), b"");
+        })
+    }
+
+    #[test]
+    fn root_path_is_added_to_every_path() {
+        Python::with_gil(|py| {
+            let connection = mounted_connection("/svc/my app", false);
+            for (uri, path, raw_path) in &[
+                (
+                    "/users/caf%C3%A9",
+                    "/svc/my app/users/caf\u{e9}",
+                    "/svc/my%20app/users/caf%C3%A9",
+                ),
+                ("/", "/svc/my app/", "/svc/my%20app/"),
+                // Even if it looks like it's already there
+                (
+                    "/svc/my%20app/x",
+                    "/svc/my app/svc/my app/x",
+                    "/svc/my%20app/svc/my%20app/x",
+                ),
+            ] {
+                let request = Request::get(*uri).body(()).unwrap();
+                let scope = build_scope(py, request, &connection);
+                assert_eq!(string(scope, "root_path"), "/svc/my app");
+                assert_eq!(string(scope, "path"), *path);
+                assert_eq!(bytes(scope, "raw_path"), raw_path.as_bytes());
+            }
+        })
+    }
+
+    #[test]
+    fn root_path_is_not_added_when_paths_include_it() {
+        Python::with_gil(|py| {
+            let connection = mounted_connection("/svc", true);
+            let request = Request::get("/svc/users").body(()).unwrap();
+            let scope = build_scope(py, request, &connection);
+            assert_eq!(string(scope, "root_path"), "/svc");
+            assert_eq!(string(scope, "path"), "/svc/users");
+            assert_eq!(bytes(scope, "raw_path"), b"/svc/users");
+        })
+    }
+
+    #[test]
+    fn root_path_is_not_added_to_the_asterisk_form() {
+        Python::with_gil(|py| {
+            let request = Request::builder()
+                .method(Method::OPTIONS)
+                .uri("*")
+                .body(())
+                .unwrap();
+            let scope = build_scope(py, request, &mounted_connection("/svc", false));
+            assert_eq!(string(scope, "path"), "*");
+            assert_eq!(bytes(scope, "raw_path"), b"*");
+        })
+    }
+
+    #[test]
+    fn http2_authority_becomes_the_host_header() {
+        Python::with_gil(|py| {
+            let request = Request::get("http://example.com:8080/h2?q")
+                .version(Version::HTTP_2)
+                .header("accept", "*/*")
+                .body(())
+                .unwrap();
+            let scope = build_scope(py, request, &tcp_connection());
+            assert_eq!(string(scope, "http_version"), "2");
+            assert_eq!(string(scope, "path"), "/h2");
+            assert_eq!(bytes(scope, "query_string"), b"q");
+            assert_eq!(
+                headers(scope),
+                vec![
+                    (b"host".to_vec(), b"example.com:8080".to_vec()),
+                    (b"accept".to_vec(), b"*/*".to_vec()),
+                ]
+            );
+        })
+    }
+
+    #[test]
+    fn host_header_is_not_duplicated_by_the_authority() {
+        Python::with_gil(|py| {
+            let request = Request::get("http://example.com/")
+                .version(Version::HTTP_2)
+                .header("host", "example.org")
+                .body(())
+                .unwrap();
+            let scope = build_scope(py, request, &tcp_connection());
+            assert_eq!(
+                headers(scope),
+                vec![(b"host".to_vec(), b"example.org".to_vec())]
+            );
+        })
+    }
+
+    #[test]
+    fn unix_socket_clients_have_no_client_address() {
+        Python::with_gil(|py| {
+            let connection = ConnectionInfo::new(
+                Address::Unix(None),
+                Address::Unix(Some(PathBuf::from("/run/app.sock"))),
+                None,
+            );
+            let request = Request::get("/").body(()).unwrap();
+            let scope = build_scope(py, request, &connection);
+            assert!(get(scope, "client").is_none());
+            let server: (String, Option<u16>) = get(scope, "server").extract().unwrap();
+            assert_eq!(server, ("/run/app.sock".to_string(), None));
+        })
+    }
+
+    #[test]
+    fn unnamed_unix_server_socket_has_no_server_address() {
+        Python::with_gil(|py| {
+            let connection = ConnectionInfo::new(Address::Unix(None), Address::Unix(None), None);
+            let request = Request::get("/").body(()).unwrap();
+            let scope = build_scope(py, request, &connection);
+            assert!(get(scope, "server").is_none());
+        })
+    }
+
+    #[test]
+    fn scheme_is_https_on_secure_connections() {
+        Python::with_gil(|py| {
+            let (parts, _) = Request::get("/").body(()).unwrap().into_parts();
+            let scope = HttpScopeBuilder::new(parts)
+                .add_provider(providers::HttpScheme { secure: true })
+                .try_into_py_dict(py)
+                .unwrap()
+                .into_ref(py);
+            assert_eq!(string(scope, "scheme"), "https");
+        })
+    }
+}
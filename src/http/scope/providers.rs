@@ -1,14 +1,28 @@
+use http::header::HOST;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use pyo3::{
     exceptions::PyValueError,
-    types::{PyBytes, PyDict, PyList, PyTuple},
+    types::{PyBytes, PyDict, PyList},
     PyResult, Python,
 };
-use std::net::SocketAddr;
+use std::sync::Arc;
 
-use crate::asgi_scope;
+use crate::{
+    asgi_scope,
+    server::{Address, ConnectionInfo, ProxyHeader, TlsInfo},
+};
 
 use super::scope_provider::HttpScopeProvider;
 
+/// The characters that are percent-encoded when a root path is added to
+/// `raw_path`: all but those that are unreserved in a URL path.
+const PATH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
 pub struct HttpVersion;
 impl HttpScopeProvider for HttpVersion {
     fn add_scope(&self, parts: &http::request::Parts, scope_dict: &PyDict) -> PyResult<()> {
@@ -31,44 +45,76 @@ impl HttpScopeProvider for HttpMethod {
     }
 }
 
-pub struct HttpScheme;
+pub struct HttpScheme {
+    pub secure: bool,
+}
 impl HttpScopeProvider for HttpScheme {
     fn add_scope(&self, parts: &http::request::Parts, scope_dict: &PyDict) -> PyResult<()> {
-        if let Some(scheme) = parts.uri.scheme_str() {
-            scope_dict.set_item("scheme", scheme)
-        } else {
-            Ok(())
-        }
+        let scheme = match parts.uri.scheme_str() {
+            _ if self.secure => "https",
+            Some("https") => "https",
+            _ => "http",
+        };
+        scope_dict.set_item("scheme", scheme)
     }
 }
 
-pub struct HttpPath;
+/// The `path`, `raw_path` and `root_path` of a request. `path` is
+/// percent-decoded, while `raw_path` is as the client sent it. As the ASGI
+/// spec now asks, both include the root path, which is added to them
+/// unless the settings say that the gateway leaves it in request paths.
+pub struct HttpPath {
+    pub root_path: String,
+    pub root_path_included: bool,
+}
 impl HttpScopeProvider for HttpPath {
     fn add_scope(&self, parts: &http::request::Parts, scope_dict: &PyDict) -> PyResult<()> {
-        scope_dict.set_item("path", parts.uri.path())
+        let raw_path = parts.uri.path();
+        let path = percent_decode_str(raw_path).decode_utf8_lossy();
+        // Paths such as `*` in `OPTIONS *` aren't under any root
+        let add_root = !self.root_path_included && raw_path.starts_with('/');
+
+        Python::with_gil(|py| {
+            if add_root {
+                let raw_root_path = utf8_percent_encode(&self.root_path, PATH_ENCODE_SET);
+                let raw_path = format!("{}{}", raw_root_path, raw_path);
+                scope_dict.set_item("path", format!("{}{}", self.root_path, path))?;
+                scope_dict.set_item("raw_path", PyBytes::new(py, raw_path.as_bytes()))?;
+            } else {
+                scope_dict.set_item("path", path.as_ref())?;
+                scope_dict.set_item("raw_path", PyBytes::new(py, raw_path.as_bytes()))?;
+            }
+            scope_dict.set_item("root_path", &self.root_path)
+        })
     }
 }
 
+/// The query string, exactly as the client sent it.
 pub struct HttpQueryString;
 impl HttpScopeProvider for HttpQueryString {
     fn add_scope(&self, parts: &http::request::Parts, scope_dict: &PyDict) -> PyResult<()> {
-        if let Some(query_str) = parts.uri.query() {
-            let percent_encoded = percent_encoding::utf8_percent_encode(
-                query_str,
-                percent_encoding::NON_ALPHANUMERIC,
-            );
-            scope_dict.set_item("method", percent_encoded.to_string().as_bytes())
-        } else {
-            Ok(())
-        }
+        let query = parts.uri.query().unwrap_or("");
+        Python::with_gil(|py| {
+            scope_dict.set_item("query_string", PyBytes::new(py, query.as_bytes()))
+        })
     }
 }
 
+/// The request headers, with lowercased names. HTTP/2 requests carry the
+/// host in the `:authority` pseudo-header, which is given to the app as
+/// `host` when there is no `host` header, as the spec asks.
 pub struct HttpHeaders;
 impl HttpScopeProvider for HttpHeaders {
     fn add_scope(&self, parts: &http::request::Parts, scope_dict: &PyDict) -> PyResult<()> {
         Python::with_gil(|py| {
             let py_headers_list = PyList::empty(py);
+            if let Some(authority) = parts.uri.authority() {
+                if !parts.headers.contains_key(HOST) {
+                    let header_name = PyBytes::new(py, HOST.as_str().as_bytes());
+                    let header_value = PyBytes::new(py, authority.as_str().as_bytes());
+                    py_headers_list.append((header_name, header_value))?;
+                }
+            }
             for (header_name, header_value) in &parts.headers {
                 let header_name = PyBytes::new(py, header_name.as_str().to_lowercase().as_bytes());
                 let header_value = PyBytes::new(py, header_value.as_bytes());
@@ -80,35 +126,107 @@ impl HttpScopeProvider for HttpHeaders {
 }
 
 pub enum HttpAddress {
-    ClientSocket(SocketAddr),
-    ServerSocket(SocketAddr),
+    ClientSocket(Address),
+    ServerSocket(Address),
+    /// The host and port that a proxy says the client sent the request to.
+    ServerHost(String, u16),
+}
+
+impl HttpAddress {
+    /// The server's address, as the client sees it.
+    pub fn server(connection: &ConnectionInfo) -> Self {
+        match connection.forwarded_host() {
+            Some((host, port)) => HttpAddress::ServerHost(host.clone(), *port),
+            None => HttpAddress::ServerSocket(connection.server_addr().clone()),
+        }
+    }
 }
 
 impl asgi_scope::ScopeProvider for HttpAddress {
     fn add_scope(&self, scope_dict: &PyDict) -> PyResult<()> {
+        // Unix sockets are reported as `[path, None]`, and as `None` if they
+        // are unnamed. The spec has no form for a Unix client, so it is
+        // `None` too.
         match self {
-            HttpAddress::ClientSocket(addr) => {
+            HttpAddress::ClientSocket(Address::Inet(addr)) => {
                 scope_dict.set_item("client", (addr.ip().to_string(), addr.port()))
             }
-            HttpAddress::ServerSocket(addr) => {
+            HttpAddress::ServerSocket(Address::Inet(addr)) => {
                 scope_dict.set_item("server", (addr.ip().to_string(), addr.port()))
             }
+            HttpAddress::ServerSocket(Address::Unix(Some(path))) => {
+                scope_dict.set_item("server", (path.to_string_lossy(), None::<u16>))
+            }
+            HttpAddress::ServerHost(host, port) => scope_dict.set_item("server", (host, port)),
+            HttpAddress::ClientSocket(Address::Unix(_)) => {
+                scope_dict.set_item("client", None::<(String, u16)>)
+            }
+            HttpAddress::ServerSocket(Address::Unix(None)) => {
+                scope_dict.set_item("server", None::<(String, u16)>)
+            }
         }
     }
 }
 
-pub struct HttpServer(Vec<SocketAddr>);
-impl asgi_scope::ScopeProvider for HttpServer {
+/// The ASGI `tls` extension, which is only added for connections secured
+/// with TLS.
+pub struct TlsExtension(pub Option<Arc<TlsInfo>>);
+
+impl asgi_scope::ScopeProvider for TlsExtension {
+    fn add_scope(&self, scope_dict: &PyDict) -> PyResult<()> {
+        let tls_info = match &self.0 {
+            Some(tls_info) => tls_info,
+            None => return Ok(()),
+        };
+
+        Python::with_gil(|py| {
+            if !scope_dict.contains("extensions")? {
+                scope_dict.set_item("extensions", PyDict::new(py))?;
+            }
+
+            let tls_dict = PyDict::new(py);
+            tls_dict.set_item("server_cert", &tls_info.server_cert)?;
+            tls_dict.set_item("client_cert_chain", &tls_info.client_cert_chain)?;
+            tls_dict.set_item("client_cert_name", &tls_info.client_cert_name)?;
+            // Certificates that fail verification are rejected in the handshake
+            tls_dict.set_item("client_cert_error", py.None())?;
+            tls_dict.set_item("tls_version", tls_info.tls_version)?;
+            tls_dict.set_item("cipher_suite", tls_info.cipher_suite)?;
+
+            let extensions: &PyDict = scope_dict.get_item("extensions").unwrap().downcast()?;
+            extensions.set_item("tls", tls_dict)
+        })
+    }
+}
+
+/// A `proxy_protocol` extension with the version of the PROXY protocol
+/// header that the connection started with, and the type and value of its
+/// TLVs, which load balancers use to pass on details such as the TLS
+/// session or the endpoint the client came through.
+pub struct ProxyProtocolExtension(pub Option<Arc<ProxyHeader>>);
+
+impl asgi_scope::ScopeProvider for ProxyProtocolExtension {
     fn add_scope(&self, scope_dict: &PyDict) -> PyResult<()> {
-        let HttpServer(server_addresses) = self;
+        let proxy_header = match &self.0 {
+            Some(proxy_header) => proxy_header,
+            None => return Ok(()),
+        };
+
         Python::with_gil(|py| {
-            let py_server_tuple = PyTuple::new(
-                py,
-                server_addresses
-                    .into_iter()
-                    .map(|addr| (addr.ip().to_string(), addr.port())),
-            );
-            scope_dict.set_item("server", py_server_tuple)
+            if !scope_dict.contains("extensions")? {
+                scope_dict.set_item("extensions", PyDict::new(py))?;
+            }
+
+            let proxy_dict = PyDict::new(py);
+            proxy_dict.set_item("version", proxy_header.version)?;
+            let tlvs = PyList::empty(py);
+            for (kind, value) in &proxy_header.tlvs {
+                tlvs.append((*kind, PyBytes::new(py, value)))?;
+            }
+            proxy_dict.set_item("tlvs", tlvs)?;
+
+            let extensions: &PyDict = scope_dict.get_item("extensions").unwrap().downcast()?;
+            extensions.set_item("proxy_protocol", proxy_dict)
         })
     }
 }
@@ -4,8 +4,12 @@ mod asgi_scope;
 mod error;
 mod helpers;
 mod http;
+mod lifespan;
+mod runtime_task;
 mod server;
+mod websocket;
 
+use futures::FutureExt;
 use pyo3::prelude::*;
 use pyo3::wrap_pyfunction;
 use server::Settings;
@@ -15,11 +19,31 @@ use crate::asgi_driver::*;
 #[pyfunction]
 fn start_server(py: Python, asgi_app: Py<PyAny>, settings: Py<Settings>) -> PyResult<PyObject> {
     let driver = AsgiDriver::new(asgi_app);
-    let py_none = py.None();
-    pyo3_asyncio::tokio::into_coroutine(py, async {
-        server::start_http_server(driver, settings).await;
-        Ok(py_none)
-    })
+    runtime_task::spawn(py, server::start_http_server(driver, settings).boxed())
+}
+
+/// Run `settings.workers` processes of `worker_command` which serve
+/// connections on sockets bound by this process, restarting any that exit.
+#[pyfunction]
+fn start_supervisor(
+    py: Python,
+    settings: Py<Settings>,
+    worker_command: Vec<String>,
+) -> PyResult<PyObject> {
+    runtime_task::spawn(
+        py,
+        server::start_supervisor(settings, worker_command).boxed(),
+    )
+}
+
+/// Shut down every server started by `start_server` in this process
+/// gracefully, as SIGTERM does. `Server` objects are shut down with their
+/// own `shutdown` method. `timeout` overrides the `shutdown_timeout` setting.
+#[pyfunction]
+fn shutdown(timeout: Option<f64>) -> PyResult<()> {
+    let timeout = timeout.map(server::shutdown_timeout).transpose()?;
+    server::request_shutdown(server::ShutdownRequest { timeout });
+    Ok(())
 }
 
 /// A Python module implemented in Rust.
@@ -27,12 +51,12 @@ fn start_server(py: Python, asgi_app: Py<PyAny>, settings: Py<Settings>) -> PyRe
 fn asgi_server(py: Python, m: &PyModule) -> PyResult<()> {
     pyo3_log::init();
     pyo3_asyncio::try_init(py)?;
-    // Tokio needs explicit initialization before any pyo3-asyncio conversions.
-    // The module import is a prime place to do this.
-    pyo3_asyncio::tokio::init_multi_thread_once();
 
     m.add_function(wrap_pyfunction!(start_server, m)?)?;
+    m.add_function(wrap_pyfunction!(start_supervisor, m)?)?;
+    m.add_function(wrap_pyfunction!(shutdown, m)?)?;
     m.add_class::<server::Settings>()?;
+    m.add_class::<server::ServerHandle>()?;
 
     Ok(())
 }
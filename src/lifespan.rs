@@ -0,0 +1,215 @@
+mod failure_message;
+mod shutdown_message;
+mod startup_message;
+
+use std::{convert::TryFrom, fmt, str::FromStr, time::Duration};
+
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, Either},
+    FutureExt, SinkExt, StreamExt,
+};
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    types::{IntoPyDict, PyDict},
+    Py, PyErr, PyResult, Python,
+};
+
+use self::{
+    shutdown_message::{LifespanShutdown, LifespanShutdownComplete, LifespanShutdownFailed},
+    startup_message::{LifespanStartup, LifespanStartupComplete, LifespanStartupFailed},
+};
+use crate::asgi_driver::AsgiDriver;
+use crate::asgi_message::AsgiMessage;
+use crate::asgi_scope;
+use crate::helpers::{into_py_message, TryIntoPyDict};
+
+/// How long to wait for the app's `lifespan` call to return after it has
+/// finished shutting down.
+const APP_EXIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Controls whether the server runs the ASGI lifespan protocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LifespanMode {
+    /// Run the protocol, but carry on without it if the app doesn't support it.
+    Auto,
+    /// Run the protocol, and refuse to start if the app doesn't support it.
+    On,
+    /// Never run the protocol.
+    Off,
+}
+
+impl FromStr for LifespanMode {
+    type Err = PyErr;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(LifespanMode::Auto),
+            "on" => Ok(LifespanMode::On),
+            "off" => Ok(LifespanMode::Off),
+            _ => Err(PyValueError::new_err(format!(
+                "'{}' is not a valid lifespan mode: expected 'auto', 'on' or 'off'",
+                value
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for LifespanMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LifespanMode::Auto => "auto",
+            LifespanMode::On => "on",
+            LifespanMode::Off => "off",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+enum LifespanReply {
+    Message(Py<PyDict>),
+    AppExited(PyResult<()>),
+}
+
+/// A running `lifespan` context of the ASGI app.
+///
+/// The context is opened by `Lifespan::startup`, which only returns once
+/// the app has finished starting up, and is closed again by
+/// `Lifespan::shutdown`.
+pub struct Lifespan {
+    results_from_rust: mpsc::Sender<Py<PyDict>>,
+    messages_to_rust: mpsc::Receiver<Py<PyDict>>,
+    app_exit: oneshot::Receiver<PyResult<()>>,
+}
+
+impl Lifespan {
+    /// Open a `lifespan` context and wait for the app to handle
+    /// `lifespan.startup`.
+    ///
+    /// Returns `None` if the lifespan protocol is disabled, or not supported
+    /// by the app in `auto` mode. An error is returned if startup failed, in
+    /// which case the server must not start.
+    pub async fn startup(
+        asgi_driver: &AsgiDriver,
+        mode: LifespanMode,
+    ) -> PyResult<Option<Lifespan>> {
+        if mode == LifespanMode::Off {
+            return Ok(None);
+        }
+
+        log::info!("Waiting for application startup.");
+        let mut lifespan = Lifespan::new(asgi_driver)?;
+        lifespan.send(LifespanStartup).await;
+
+        match lifespan.receive().await {
+            LifespanReply::Message(message_dict) => Python::with_gil(|py| {
+                let message_dict = message_dict.as_ref(py);
+                if LifespanStartupFailed::matches_message_type(message_dict)? {
+                    let failed = LifespanStartupFailed::try_from(message_dict)?;
+                    log::error!("Application startup failed. {}", failed.message());
+                    Err(PyRuntimeError::new_err(format!(
+                        "Application startup failed: {}",
+                        failed.message()
+                    )))
+                } else {
+                    LifespanStartupComplete::try_from(message_dict)?;
+                    log::info!("Application startup complete.");
+                    Ok(Some(lifespan))
+                }
+            }),
+            LifespanReply::AppExited(Ok(_)) => Ok(None),
+            LifespanReply::AppExited(Err(py_err)) if mode == LifespanMode::On => {
+                log::error!("Exception in 'lifespan' protocol: {}", py_err);
+                Err(py_err)
+            }
+            LifespanReply::AppExited(Err(_)) => {
+                log::info!("ASGI 'lifespan' protocol appears unsupported.");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Send `lifespan.shutdown` to the app and wait for it to finish
+    /// shutting down.
+    pub async fn shutdown(mut self) {
+        log::info!("Waiting for application shutdown.");
+        self.send(LifespanShutdown).await;
+
+        match self.receive().await {
+            LifespanReply::Message(message_dict) => {
+                Python::with_gil(|py| {
+                    let message_dict = message_dict.as_ref(py);
+                    if LifespanShutdownFailed::matches_message_type(message_dict)? {
+                        let failed = LifespanShutdownFailed::try_from(message_dict)?;
+                        log::error!("Application shutdown failed. {}", failed.message());
+                    } else {
+                        LifespanShutdownComplete::try_from(message_dict)?;
+                        log::info!("Application shutdown complete.");
+                    }
+                    Ok(())
+                })
+                .unwrap_or_else(|py_err: PyErr| {
+                    log::error!("Application shutdown failed. {}", py_err)
+                });
+
+                // Let the app's `send` call and the lifespan call itself
+                // return, so that they don't touch Python while the
+                // interpreter is being finalized.
+                let app_exit = tokio::time::timeout(APP_EXIT_TIMEOUT, self.app_exit).await;
+                if let Ok(Ok(Err(py_err))) = app_exit {
+                    log::error!("Exception in 'lifespan' protocol: {}", py_err)
+                }
+            }
+            LifespanReply::AppExited(Ok(_)) => log::info!("Application shutdown complete."),
+            LifespanReply::AppExited(Err(py_err)) => {
+                log::error!("Exception in 'lifespan' protocol: {}", py_err)
+            }
+        }
+    }
+
+    fn new(asgi_driver: &AsgiDriver) -> PyResult<Lifespan> {
+        // These channels will be used to communicate between python and rust
+        let (results_from_py, messages_to_rust) = mpsc::channel::<Py<PyDict>>(1);
+        let (results_from_rust, messages_to_py) = mpsc::channel::<Py<PyDict>>(1);
+        let (app_exit_emitter, app_exit) = oneshot::channel::<PyResult<()>>();
+
+        let asgi_context = Python::with_gil(|py| {
+            let scope_dict = asgi_scope::ScopeBuilder::new()
+                .add_provider(asgi_scope::providers::Type::Lifespan)
+                .add_provider(asgi_scope::providers::AsgiVersion {
+                    spec_version: "2.0",
+                })
+                .add_provider(asgi_scope::providers::State::Lifespan(asgi_driver.state()))
+                .try_into_py_dict(py)?;
+            asgi_driver.create_context(scope_dict.as_ref(py), messages_to_py, results_from_py)
+        })?;
+
+        pyo3_asyncio::tokio::get_runtime().spawn(asgi_context.map(move |result| {
+            app_exit_emitter.send(result.map(|_| ())).unwrap_or(());
+        }));
+
+        Ok(Lifespan {
+            results_from_rust,
+            messages_to_rust,
+            app_exit,
+        })
+    }
+
+    async fn send(&mut self, message: impl IntoPyDict) {
+        self.results_from_rust
+            .send(into_py_message(message))
+            .await
+            .unwrap_or(());
+    }
+
+    async fn receive(&mut self) -> LifespanReply {
+        let next_message = self.messages_to_rust.next();
+        let app_result = match future::select(next_message, &mut self.app_exit).await {
+            Either::Left((Some(message_dict), _)) => return LifespanReply::Message(message_dict),
+            Either::Left((None, app_exit)) => app_exit.await,
+            Either::Right((app_result, _)) => app_result,
+        };
+
+        LifespanReply::AppExited(app_result.unwrap_or(Ok(())))
+    }
+}
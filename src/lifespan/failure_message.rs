@@ -0,0 +1,9 @@
+use pyo3::{types::PyDict, PyResult};
+
+/// Read the optional `message` of a `lifespan.*.failed` event.
+pub fn get_message(message_dict: &PyDict) -> PyResult<String> {
+    match message_dict.get_item("message") {
+        Some(message) if !message.is_none() => message.extract::<String>(),
+        _ => Ok(String::new()),
+    }
+}
@@ -0,0 +1,64 @@
+use std::convert::TryFrom;
+
+use pyo3::{
+    types::{IntoPyDict, PyDict},
+    PyErr, Python,
+};
+
+use super::failure_message;
+use crate::asgi_message::AsgiMessage;
+
+pub struct LifespanShutdown;
+
+impl IntoPyDict for LifespanShutdown {
+    fn into_py_dict(self, py: Python<'_>) -> &PyDict {
+        let dict = PyDict::new(py);
+
+        dict.set_item("type", "lifespan.shutdown").unwrap_or(());
+
+        dict
+    }
+}
+
+pub struct LifespanShutdownComplete;
+
+impl AsgiMessage for LifespanShutdownComplete {
+    fn message_type() -> &'static str {
+        "lifespan.shutdown.complete"
+    }
+}
+
+impl TryFrom<&PyDict> for LifespanShutdownComplete {
+    type Error = PyErr;
+
+    fn try_from(message_dict: &PyDict) -> Result<Self, Self::Error> {
+        LifespanShutdownComplete::validate_message_type(message_dict)
+            .map(|_| LifespanShutdownComplete)
+    }
+}
+
+pub struct LifespanShutdownFailed {
+    message: String,
+}
+
+impl LifespanShutdownFailed {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl AsgiMessage for LifespanShutdownFailed {
+    fn message_type() -> &'static str {
+        "lifespan.shutdown.failed"
+    }
+}
+
+impl TryFrom<&PyDict> for LifespanShutdownFailed {
+    type Error = PyErr;
+
+    fn try_from(message_dict: &PyDict) -> Result<Self, Self::Error> {
+        LifespanShutdownFailed::validate_message_type(message_dict)
+            .and_then(|_| failure_message::get_message(message_dict))
+            .map(|message| LifespanShutdownFailed { message })
+    }
+}
@@ -0,0 +1,64 @@
+use std::convert::TryFrom;
+
+use pyo3::{
+    types::{IntoPyDict, PyDict},
+    PyErr, Python,
+};
+
+use super::failure_message;
+use crate::asgi_message::AsgiMessage;
+
+pub struct LifespanStartup;
+
+impl IntoPyDict for LifespanStartup {
+    fn into_py_dict(self, py: Python<'_>) -> &PyDict {
+        let dict = PyDict::new(py);
+
+        dict.set_item("type", "lifespan.startup").unwrap_or(());
+
+        dict
+    }
+}
+
+pub struct LifespanStartupComplete;
+
+impl AsgiMessage for LifespanStartupComplete {
+    fn message_type() -> &'static str {
+        "lifespan.startup.complete"
+    }
+}
+
+impl TryFrom<&PyDict> for LifespanStartupComplete {
+    type Error = PyErr;
+
+    fn try_from(message_dict: &PyDict) -> Result<Self, Self::Error> {
+        LifespanStartupComplete::validate_message_type(message_dict)
+            .map(|_| LifespanStartupComplete)
+    }
+}
+
+pub struct LifespanStartupFailed {
+    message: String,
+}
+
+impl LifespanStartupFailed {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl AsgiMessage for LifespanStartupFailed {
+    fn message_type() -> &'static str {
+        "lifespan.startup.failed"
+    }
+}
+
+impl TryFrom<&PyDict> for LifespanStartupFailed {
+    type Error = PyErr;
+
+    fn try_from(message_dict: &PyDict) -> Result<Self, Self::Error> {
+        LifespanStartupFailed::validate_message_type(message_dict)
+            .and_then(|_| failure_message::get_message(message_dict))
+            .map(|message| LifespanStartupFailed { message })
+    }
+}
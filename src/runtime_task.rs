@@ -0,0 +1,105 @@
+#[cfg(unix)]
+use std::{
+    os::unix::{io::AsRawFd, net::UnixStream},
+    sync::{Arc, Mutex},
+};
+
+use futures::future::BoxFuture;
+#[cfg(unix)]
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+
+#[cfg(unix)]
+type TaskResult = Arc<Mutex<Option<PyResult<()>>>>;
+
+/// Run `future` on the shared Tokio runtime, and return an asyncio future
+/// which completes with its result.
+///
+/// `pyo3_asyncio::tokio::into_coroutine` resolves its future from a Tokio
+/// thread, which has to take the GIL again after waking the event loop. If
+/// that result was the last thing the program waited for, the interpreter may
+/// already be finalizing by then, which aborts the process. Instead, the task
+/// only closes its end of a socket pair when it is done, and the event loop
+/// picks up the result itself when the other end becomes readable.
+#[cfg(unix)]
+pub fn spawn(py: Python, future: BoxFuture<'static, PyResult<()>>) -> PyResult<PyObject> {
+    init_runtime();
+    let event_loop = pyo3_asyncio::get_event_loop(py);
+    let asyncio_future = event_loop.call_method0("create_future")?;
+    let (done_receiver, done_sender) = UnixStream::pair()?;
+    let result = TaskResult::default();
+
+    let done_fd = done_receiver.as_raw_fd();
+    let on_done = TaskDone {
+        asyncio_future: asyncio_future.into(),
+        result: result.clone(),
+        done_receiver: Mutex::new(Some(done_receiver)),
+    };
+    event_loop.call_method1("add_reader", (done_fd, Py::new(py, on_done)?))?;
+
+    pyo3_asyncio::tokio::get_runtime().spawn(async move {
+        // Closed after the result is stored, or if the future panics
+        let _done_sender = done_sender;
+        let future_result = future.await;
+        *result.lock().unwrap() = Some(future_result);
+    });
+
+    Ok(asyncio_future.into())
+}
+
+#[cfg(not(unix))]
+pub fn spawn(py: Python, future: BoxFuture<'static, PyResult<()>>) -> PyResult<PyObject> {
+    init_runtime();
+    let py_none = py.None();
+    pyo3_asyncio::tokio::into_coroutine(py, async move {
+        future.await?;
+        Ok(py_none)
+    })
+}
+
+/// Start the Tokio runtime the first time a server is, rather than when the
+/// module is imported. Until then the process has no other threads, so the
+/// environment can safely be changed, such as by `asgi_server.worker`.
+fn init_runtime() {
+    pyo3_asyncio::tokio::init_multi_thread_once();
+}
+
+/// The event loop's reader callback for a task's socket, which completes the
+/// task's asyncio future.
+#[cfg(unix)]
+#[pyclass]
+struct TaskDone {
+    asyncio_future: PyObject,
+    result: TaskResult,
+    done_receiver: Mutex<Option<UnixStream>>,
+}
+
+#[cfg(unix)]
+#[pymethods]
+impl TaskDone {
+    #[call]
+    fn __call__(&self, py: Python) -> PyResult<()> {
+        let done_receiver = match self.done_receiver.lock().unwrap().take() {
+            Some(done_receiver) => done_receiver,
+            None => return Ok(()),
+        };
+        let asyncio_future = self.asyncio_future.as_ref(py);
+        asyncio_future
+            .call_method0("get_loop")?
+            .call_method1("remove_reader", (done_receiver.as_raw_fd(),))?;
+        drop(done_receiver);
+
+        if asyncio_future.call_method0("cancelled")?.is_true()? {
+            return Ok(());
+        }
+        match self.result.lock().unwrap().take() {
+            Some(Ok(())) => asyncio_future.call_method1("set_result", (py.None(),))?,
+            Some(Err(err)) => asyncio_future.call_method1("set_exception", (err.instance(py),))?,
+            None => asyncio_future.call_method1(
+                "set_exception",
+                (PyRuntimeError::new_err("The server task panicked").instance(py),),
+            )?,
+        };
+        Ok(())
+    }
+}
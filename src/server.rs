@@ -1,40 +1,377 @@
+mod address;
+mod connection;
+mod handle;
+mod incoming;
+mod limits;
+mod listener;
+mod proxy;
+mod proxy_protocol;
+mod rate_limit;
+#[cfg(unix)]
+mod reloader;
 mod settings;
+mod shutdown;
+mod stream;
+#[cfg(unix)]
+mod supervisor;
+mod timeouts;
+mod tls;
+mod worker;
 
-use std::convert::Infallible;
-use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::io::OwnedFd;
+use std::{convert::Infallible, future::Future, io, pin::Pin, sync::Arc, time::Duration};
+
+use futures::{channel::oneshot, future};
 
 use crate::asgi_driver::AsgiDriver;
 use crate::http;
-use hyper::server::conn::{AddrIncoming, AddrStream};
-use hyper::server::Builder;
+use crate::lifespan::{Lifespan, LifespanMode};
+use crate::websocket;
+use hyper::server::Server;
 use hyper::service::{make_service_fn, service_fn};
-use log;
-use pyo3::Python;
+use hyper::StatusCode;
+use pyo3::{PyResult, Python};
+
+pub use address::Address;
+pub use connection::{Connection, ConnectionInfo};
+pub use handle::ServerHandle;
+use incoming::Incoming;
+use limits::{ConnectionLimit, LimitConfig, RequestLimit, RETRY_AFTER_SECONDS};
+use listener::BindConfig;
+#[cfg(test)]
+pub use proxy::Forwarded;
+use proxy::ProxyConfig;
+pub use proxy_protocol::ProxyHeader;
+use rate_limit::{RateLimitConfig, RateLimiter};
 pub use settings::Settings;
+pub use shutdown::ConnectionTasks;
+pub use shutdown::{request_shutdown, shutdown_timeout, ShutdownRequest};
+use shutdown::{ShutdownConfig, ShutdownRequests};
+#[cfg(unix)]
+pub use supervisor::start_supervisor;
+pub use timeouts::{DataRateMonitor, MinDataRate};
+use timeouts::{GuardedBody, TimeoutConfig};
+pub use tls::TlsInfo;
+use tls::{TlsAcceptor, TlsConfig};
+use worker::{WorkerConfig, WorkerLink};
 
-pub async fn start_http_server(driver: AsgiDriver, settings: pyo3::Py<Settings>) {
-    log::info!("Starting asgi-server 0.0.1");
+pub async fn start_http_server(driver: AsgiDriver, settings: pyo3::Py<Settings>) -> PyResult<()> {
     let settings = Python::with_gil(|py| {
-        let settings: &Settings = &*settings.borrow(py);
+        let settings: &Settings = &settings.borrow(py);
         settings.clone()
     });
-    let settings_clone = settings.clone();
-
-    let make_service = make_service_fn(move |conn: &AddrStream| {
-        let driver = driver.clone();
-        let remote_addr = conn.remote_addr();
-        let server_addr = SocketAddr::from(&settings_clone);
-        let service = service_fn(move |request| {
-            http::handle_request(server_addr, remote_addr, request, driver.clone())
+
+    let server = BoundServer::bind(driver, &settings).await?;
+    server.serve(ShutdownRequests::new(), || ()).await;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn start_supervisor(
+    _settings: pyo3::Py<Settings>,
+    _worker_command: Vec<String>,
+) -> PyResult<()> {
+    Err(pyo3::exceptions::PyRuntimeError::new_err(
+        "Running multiple workers is not supported on this platform",
+    ))
+}
+
+/// A server whose app has started up and whose sockets are bound, ready to
+/// serve connections.
+pub struct BoundServer {
+    driver: AsgiDriver,
+    incoming: Incoming,
+    tls_acceptor: Option<TlsAcceptor>,
+    lifespan: Option<Lifespan>,
+    local_addrs: Vec<Address>,
+    shutdown_config: ShutdownConfig,
+    http_config: http::HttpConfig,
+    timeout_config: TimeoutConfig,
+    limit_config: LimitConfig,
+    rate_limit_config: RateLimitConfig,
+    proxy_config: ProxyConfig,
+    worker_link: Option<Arc<WorkerLink>>,
+}
+
+impl BoundServer {
+    /// Run the app's lifespan startup, then bind the server's sockets.
+    pub async fn bind(driver: AsgiDriver, settings: &Settings) -> PyResult<Self> {
+        log::info!("Starting asgi-server 0.0.1");
+
+        let tls_acceptor = TlsConfig::from(settings).acceptor().await?;
+        let lifespan = Lifespan::startup(&driver, LifespanMode::from(settings)).await?;
+
+        let listeners = match BindConfig::from(settings).bind().await {
+            Ok(listeners) => listeners,
+            Err(io_err) => {
+                log::error!("Could not bind to {}", io_err);
+                if let Some(lifespan) = lifespan {
+                    lifespan.shutdown().await;
+                }
+                return Err(io_err.into());
+            }
+        };
+        let timeout_config = TimeoutConfig::from(settings);
+        let limit_config = LimitConfig::from(settings);
+        let proxy_config = ProxyConfig::from(settings);
+        let incoming = Incoming::new(
+            listeners,
+            tls_acceptor.clone(),
+            proxy_config.proxy_protocol,
+            timeout_config.clone(),
+            limit_config.max_connections.map(ConnectionLimit::new),
+        );
+        let local_addrs = incoming.local_addrs()?;
+
+        Ok(BoundServer {
+            driver,
+            incoming,
+            tls_acceptor,
+            lifespan,
+            local_addrs,
+            shutdown_config: ShutdownConfig::from(settings),
+            http_config: http::HttpConfig::from(settings),
+            timeout_config,
+            limit_config,
+            rate_limit_config: RateLimitConfig::from(settings),
+            proxy_config,
+            worker_link: WorkerLink::from_config(&WorkerConfig::from(settings)).map(Arc::new),
+        })
+    }
+
+    /// The addresses that the server is listening at.
+    pub fn local_addrs(&self) -> &[Address] {
+        &self.local_addrs
+    }
+
+    /// Duplicates of the listening sockets.
+    #[cfg(unix)]
+    pub fn try_clone_fds(&self) -> io::Result<Vec<OwnedFd>> {
+        self.incoming.try_clone_fds()
+    }
+
+    /// Serve connections until a shutdown is requested, then drain them and
+    /// run the app's lifespan shutdown. `on_shutdown` is called as soon as
+    /// the server stops accepting connections.
+    pub async fn serve(self, mut shutdown_requests: ShutdownRequests, on_shutdown: impl FnOnce()) {
+        let BoundServer {
+            driver,
+            incoming,
+            tls_acceptor,
+            lifespan,
+            local_addrs,
+            shutdown_config,
+            http_config,
+            timeout_config,
+            limit_config,
+            rate_limit_config,
+            proxy_config,
+            worker_link,
+        } = self;
+
+        let tls_watcher = tls_acceptor.map(|tls_acceptor| tokio::spawn(tls_acceptor.watch()));
+        let scheme = if incoming.is_secure() {
+            "https"
+        } else {
+            "http"
+        };
+
+        let service_worker_link = worker_link.clone();
+        let request_limit = limit_config
+            .max_concurrent_requests
+            .map(|max_concurrent_requests| {
+                RequestLimit::new(max_concurrent_requests, limit_config.request_queue_timeout)
+            });
+        let rate_limiter = if rate_limit_config.is_enabled() {
+            Some(Arc::new(RateLimiter::new(&rate_limit_config)))
+        } else {
+            None
+        };
+        let proxy_config = Arc::new(proxy_config);
+        let connection_tasks = ConnectionTasks::new();
+        let service_connection_tasks = connection_tasks.clone();
+        let make_service = make_service_fn(move |conn: &Connection| {
+            let driver = driver.clone();
+            let connection_tasks = service_connection_tasks.clone();
+            let http_config = http_config.clone();
+            let worker_link = service_worker_link.clone();
+            let request_limit = request_limit.clone();
+            let rate_limiter = rate_limiter.clone();
+            let proxy_config = proxy_config.clone();
+            let connection = conn.info().clone();
+            let active_requests = conn.active_requests().clone();
+            let service = service_fn(move |request| {
+                let request_guard = active_requests.start();
+                let active_requests = active_requests.clone();
+                let connection = proxy_config.forwarded(&connection, request.headers());
+                let rate_limited = match (&rate_limiter, connection.remote_addr().ip()) {
+                    (Some(rate_limiter), Some(ip)) => {
+                        rate_limiter.check(ip, request.uri().path()).err()
+                    }
+                    _ => None,
+                };
+                let request_limit = request_limit.clone();
+                let driver = driver.clone();
+                let http_config = http_config.clone();
+                let connection_tasks = connection_tasks.clone();
+                let worker_link = worker_link.clone();
+                async move {
+                    // The request keeps its place under the limit until its
+                    // response has been sent
+                    let permit = match (rate_limited, &request_limit) {
+                        (Some(retry_after_seconds), _) => {
+                            log::debug!("Rate limited {}", connection.remote_addr());
+                            Err((StatusCode::TOO_MANY_REQUESTS, retry_after_seconds))
+                        }
+                        (None, Some(request_limit)) => request_limit
+                            .acquire()
+                            .await
+                            .map(Some)
+                            .ok_or((StatusCode::SERVICE_UNAVAILABLE, RETRY_AFTER_SECONDS)),
+                        (None, None) => Ok(None),
+                    };
+                    let mut permit = match permit {
+                        Ok(permit) => permit,
+                        Err((status, retry_after_seconds)) => {
+                            let response = http::retry_later(request, status, retry_after_seconds);
+                            return Ok::<_, Infallible>(
+                                response.map(|body| GuardedBody::new(body, (request_guard, None))),
+                            );
+                        }
+                    };
+                    // Only requests that are handled count towards the
+                    // worker's max_requests
+                    if let Some(worker_link) = &worker_link {
+                        worker_link.request_received();
+                    }
+
+                    let response = if websocket::is_websocket_request(&request) {
+                        // The session keeps the request's place under the
+                        // limit until it ends
+                        websocket::handle_request(
+                            connection,
+                            request,
+                            driver,
+                            connection_tasks,
+                            permit.take(),
+                        )
+                        .await?
+                    } else {
+                        http::handle_request(connection, request, driver, http_config).await?
+                    };
+                    if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+                        active_requests.upgrade();
+                    }
+                    Ok(response.map(|body| GuardedBody::new(body, (request_guard, permit))))
+                }
+            });
+
+            async move { Ok::<_, Infallible>(service) }
         });
 
-        async move { Ok::<_, Infallible>(service) }
-    });
+        let (begin_shutdown, shutdown_begun) = oneshot::channel::<()>();
+
+        let server = Server::builder(incoming)
+            .http1_keepalive(timeout_config.keep_alive != Some(Duration::ZERO))
+            .executor(connection_tasks.clone())
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                shutdown_begun.await.unwrap_or(());
+            });
+        tokio::pin!(server);
+        for local_addr in local_addrs {
+            log::info!("Listening at: {}://{}", scheme, local_addr);
+        }
+        if let Some(worker_link) = &worker_link {
+            worker_link.ready();
+        }
+        // Workers don't outlive their supervisor, which may have been killed
+        // before it could stop them
+        let supervisor_watcher = worker_link.clone().map(|worker_link| {
+            tokio::spawn(async move {
+                match worker_link.supervisor_exited().await {
+                    Ok(()) => {
+                        log::warn!("The supervisor has exited");
+                        request_shutdown(ShutdownRequest::default());
+                    }
+                    Err(io_err) => log::warn!("Could not watch the supervisor: {}", io_err),
+                }
+            })
+        });
+
+        let result = tokio::select! {
+            result = &mut server => result,
+            request = shutdown_requests.next() => {
+                log::info!("Shutting down");
+                // The shutdown is already under way if the supervisor exits
+                if let Some(supervisor_watcher) = &supervisor_watcher {
+                    supervisor_watcher.abort();
+                }
+                begin_shutdown.send(()).unwrap_or(());
+                on_shutdown();
+                drain(
+                    &mut server,
+                    &connection_tasks,
+                    request.timeout(&shutdown_config),
+                    &mut shutdown_requests,
+                )
+                .await
+            }
+        };
+        if let Err(e) = result {
+            log::error!("server error: {}", e);
+        }
+        connection_tasks.close_all();
+
+        if let Some(tls_watcher) = tls_watcher {
+            tls_watcher.abort();
+        }
+        if let Some(supervisor_watcher) = supervisor_watcher {
+            supervisor_watcher.abort();
+        }
+
+        if let Some(lifespan) = lifespan {
+            lifespan.shutdown().await;
+        }
+    }
+}
+
+/// Waits for the server to finish the requests it is handling, and for
+/// WebSocket sessions to end, once it has stopped accepting new
+/// connections. This gives up when the timeout runs out, or when another
+/// shutdown is requested.
+async fn drain<F>(
+    server: &mut Pin<&mut F>,
+    connection_tasks: &ConnectionTasks,
+    timeout: Option<Duration>,
+    shutdown_requests: &mut ShutdownRequests,
+) -> hyper::Result<()>
+where
+    F: Future<Output = hyper::Result<()>>,
+{
+    let timeout = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => future::pending().await,
+        }
+    };
 
-    let server = Builder::<AddrIncoming>::from(&settings).serve(make_service);
-    log::info!("Listening at: http://{}", SocketAddr::from(&settings));
+    let finished = async {
+        let result = server.await;
+        connection_tasks.wait_all().await;
+        result
+    };
 
-    if let Err(e) = server.await {
-        log::error!("server error: {}", e);
+    tokio::select! {
+        result = finished => result,
+        _ = timeout => {
+            log::warn!("Closing connections that are still open after the shutdown timeout");
+            Ok(())
+        }
+        _ = shutdown_requests.next() => {
+            log::warn!("Shutting down immediately");
+            Ok(())
+        }
     }
 }
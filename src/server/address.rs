@@ -0,0 +1,40 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
+
+/// The address of either end of a connection.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Address {
+    Inet(SocketAddr),
+    /// A Unix domain socket, which has no path if it is unnamed (as client
+    /// sockets usually are).
+    Unix(Option<PathBuf>),
+}
+
+impl Address {
+    /// The IP address, for an internet socket.
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Address::Inet(addr) => Some(addr.ip()),
+            Address::Unix(_) => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for Address {
+    fn from(addr: SocketAddr) -> Self {
+        Address::Inet(addr)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Inet(addr) => write!(f, "{}", addr),
+            Address::Unix(Some(path)) => write!(f, "unix:{}", path.display()),
+            Address::Unix(None) => write!(f, "unix"),
+        }
+    }
+}
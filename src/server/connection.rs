@@ -0,0 +1,301 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::OwnedSemaphorePermit,
+};
+use tokio_rustls::server::TlsStream;
+
+use super::{
+    address::Address,
+    proxy::Forwarded,
+    proxy_protocol::ProxyHeader,
+    stream::Stream,
+    timeouts::{ActiveRequests, ConnectionTimer, TimeoutConfig, WriteTimer},
+    tls::TlsInfo,
+};
+
+/// Details about an accepted connection, which requests on it are tagged
+/// with.
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    remote_addr: Address,
+    server_addr: Address,
+    tls: Option<Arc<TlsInfo>>,
+    secure: bool,
+    /// The host that a proxy says the client asked for, and its port.
+    forwarded_host: Option<(String, u16)>,
+    /// The PROXY protocol header that the connection started with.
+    proxy_header: Option<Arc<ProxyHeader>>,
+    /// The path that the app is mounted under.
+    root_path: String,
+    /// Whether request paths already start with `root_path`.
+    root_path_included: bool,
+}
+
+impl ConnectionInfo {
+    pub fn new(remote_addr: Address, server_addr: Address, tls: Option<Arc<TlsInfo>>) -> Self {
+        ConnectionInfo {
+            remote_addr,
+            server_addr,
+            secure: tls.is_some(),
+            tls,
+            forwarded_host: None,
+            proxy_header: None,
+            root_path: String::new(),
+            root_path_included: false,
+        }
+    }
+
+    /// The connection as a proxy says its client made it.
+    pub fn forwarded(&self, forwarded: Forwarded) -> Self {
+        let secure = forwarded.secure.unwrap_or(self.secure);
+        let default_port = if secure { 443 } else { 80 };
+        ConnectionInfo {
+            remote_addr: forwarded
+                .client
+                .map(Address::Inet)
+                .unwrap_or_else(|| self.remote_addr.clone()),
+            server_addr: self.server_addr.clone(),
+            tls: self.tls.clone(),
+            secure,
+            forwarded_host: forwarded
+                .host
+                .map(|(host, port)| (host, port.unwrap_or(default_port)))
+                .or_else(|| self.forwarded_host.clone()),
+            proxy_header: self.proxy_header.clone(),
+            root_path: forwarded
+                .root_path
+                .unwrap_or_else(|| self.root_path.clone()),
+            root_path_included: forwarded.root_path_included,
+        }
+    }
+
+    pub fn remote_addr(&self) -> &Address {
+        &self.remote_addr
+    }
+
+    pub fn server_addr(&self) -> &Address {
+        &self.server_addr
+    }
+
+    /// The host and port that a proxy says the client sent the request to,
+    /// which stand in for the server's address.
+    pub fn forwarded_host(&self) -> Option<&(String, u16)> {
+        self.forwarded_host.as_ref()
+    }
+
+    /// The path prefix that the app is mounted under, without a trailing
+    /// slash, which is empty if it is mounted at the root.
+    pub fn root_path(&self) -> &str {
+        &self.root_path
+    }
+
+    /// Whether request paths already start with the root path, rather than
+    /// having had it stripped by a proxy.
+    pub fn root_path_included(&self) -> bool {
+        self.root_path_included
+    }
+
+    /// Whether the client's connection is secure: either this one is
+    /// encrypted with TLS, or a proxy says that its connection was.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Details of the TLS session, if the connection is encrypted.
+    pub fn tls(&self) -> Option<Arc<TlsInfo>> {
+        self.tls.clone()
+    }
+
+    /// The PROXY protocol header that a load balancer sent, if it did.
+    pub fn proxy_header(&self) -> Option<Arc<ProxyHeader>> {
+        self.proxy_header.clone()
+    }
+}
+
+enum ConnectionIo {
+    Plain(Stream),
+    Tls(Box<TlsStream<Stream>>),
+}
+
+/// An accepted client connection, which may or may not be wrapped in TLS.
+pub struct Connection {
+    io: ConnectionIo,
+    info: ConnectionInfo,
+    active_requests: ActiveRequests,
+    timer: Option<ConnectionTimer>,
+    write_timer: Option<WriteTimer>,
+    /// The connection's place under the connection limit, which is given up
+    /// when it closes.
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl Connection {
+    pub fn plain(stream: Stream, remote_addr: Address, server_addr: Address) -> Self {
+        Connection {
+            io: ConnectionIo::Plain(stream),
+            info: ConnectionInfo::new(remote_addr, server_addr, None),
+            active_requests: ActiveRequests::default(),
+            timer: None,
+            write_timer: None,
+            _slot: None,
+        }
+    }
+
+    pub fn tls(
+        stream: TlsStream<Stream>,
+        tls_info: TlsInfo,
+        remote_addr: Address,
+        server_addr: Address,
+    ) -> Self {
+        Connection {
+            io: ConnectionIo::Tls(Box::new(stream)),
+            info: ConnectionInfo::new(remote_addr, server_addr, Some(Arc::new(tls_info))),
+            active_requests: ActiveRequests::default(),
+            timer: None,
+            write_timer: None,
+            _slot: None,
+        }
+    }
+
+    /// Close the connection if it goes too long without a request, or if
+    /// the client takes responses too slowly.
+    pub fn with_timeouts(self, config: &TimeoutConfig) -> Self {
+        Connection {
+            timer: Some(ConnectionTimer::new(config, self.active_requests.clone())),
+            write_timer: config.min_response_rate.map(WriteTimer::new),
+            ..self
+        }
+    }
+
+    /// Count the connection against a connection limit while it is open.
+    pub fn with_slot(self, slot: Option<OwnedSemaphorePermit>) -> Self {
+        Connection {
+            _slot: slot,
+            ..self
+        }
+    }
+
+    /// Take the client's address and the one it connected to from a PROXY
+    /// protocol header, in place of the load balancer's.
+    pub fn with_proxy_header(self, proxy_header: Option<ProxyHeader>) -> Self {
+        let proxy_header = match proxy_header {
+            Some(proxy_header) => proxy_header,
+            None => return self,
+        };
+        let info = ConnectionInfo {
+            remote_addr: proxy_header
+                .source
+                .map(Address::Inet)
+                .unwrap_or(self.info.remote_addr),
+            server_addr: proxy_header
+                .destination
+                .map(Address::Inet)
+                .unwrap_or(self.info.server_addr),
+            secure: self.info.secure || proxy_header.is_secure(),
+            proxy_header: Some(Arc::new(proxy_header)),
+            ..self.info
+        };
+        Connection { info, ..self }
+    }
+
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+
+    /// The requests in progress on the connection, which the server keeps
+    /// count of.
+    pub fn active_requests(&self) -> &ActiveRequests {
+        &self.active_requests
+    }
+
+    /// Fail a write that has been waiting on a client which is taking data
+    /// too slowly.
+    fn check_write_rate(&mut self, cx: &mut Context<'_>, written: Poll<usize>) -> io::Result<()> {
+        let expired = match &mut self.write_timer {
+            Some(write_timer) => write_timer.poll_expired(cx, written),
+            None => false,
+        };
+        if expired {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "client is taking data too slowly",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let connection = self.get_mut();
+        let filled = buf.filled().len();
+        let result = match &mut connection.io {
+            ConnectionIo::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            ConnectionIo::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        };
+
+        if let Some(timer) = &mut connection.timer {
+            let received = buf.filled().len() > filled;
+            if timer.poll_expired(cx, received) && result.is_pending() {
+                // Reading nothing tells the server that the connection has
+                // closed
+                return Poll::Ready(Ok(()));
+            }
+        }
+        result
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let connection = self.get_mut();
+        let result = match &mut connection.io {
+            ConnectionIo::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            ConnectionIo::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        };
+        let written = match &result {
+            Poll::Ready(Ok(written)) => Poll::Ready(*written),
+            Poll::Ready(Err(_)) => Poll::Ready(0),
+            Poll::Pending => Poll::Pending,
+        };
+        connection.check_write_rate(cx, written)?;
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let connection = self.get_mut();
+        let result = match &mut connection.io {
+            ConnectionIo::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            ConnectionIo::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        };
+        let flushed = if result.is_pending() {
+            Poll::Pending
+        } else {
+            Poll::Ready(0)
+        };
+        connection.check_write_rate(cx, flushed)?;
+        result
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().io {
+            ConnectionIo::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            ConnectionIo::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+#[cfg(unix)]
+use std::os::unix::io::OwnedFd;
+use std::sync::{Arc, Mutex};
+
+use futures::{channel::mpsc, future, FutureExt};
+use pyo3::{
+    exceptions::PyRuntimeError,
+    prelude::*,
+    types::{IntoPyDict, PyTuple},
+};
+use tokio::sync::watch;
+
+use super::{
+    shutdown::{shutdown_timeout, ShutdownRequest, ShutdownRequests},
+    Address, BoundServer, Settings,
+};
+use crate::{asgi_driver::AsgiDriver, runtime_task};
+
+/// An ASGI server which is started and shut down from Python, by awaiting
+/// its methods.
+#[pyclass(name = "Server", module = "asgi_server")]
+pub struct ServerHandle {
+    driver: AsgiDriver,
+    settings: Settings,
+    state: Arc<ServerState>,
+}
+
+struct ServerState {
+    status: Mutex<Status>,
+    shutdown_requests: mpsc::UnboundedSender<ShutdownRequest>,
+    closed: watch::Sender<bool>,
+    closed_receiver: watch::Receiver<bool>,
+}
+
+enum Status {
+    /// The server hasn't been started, and holds the receiving end of its
+    /// shutdown requests until it is.
+    Created(mpsc::UnboundedReceiver<ShutdownRequest>),
+    Starting,
+    Serving(Listening),
+    ShuttingDown,
+    Closed,
+}
+
+/// The sockets that a serving server is listening on.
+struct Listening {
+    addresses: Vec<Address>,
+    #[cfg(unix)]
+    fds: Vec<OwnedFd>,
+}
+
+#[pymethods]
+impl ServerHandle {
+    #[new]
+    #[args(settings = "None")]
+    fn new(app: PyObject, settings: Option<Settings>) -> Self {
+        let (shutdown_requests, shutdown_receiver) = mpsc::unbounded();
+        let (closed, closed_receiver) = watch::channel(false);
+        ServerHandle {
+            driver: AsgiDriver::new(app),
+            settings: settings.unwrap_or_default(),
+            state: Arc::new(ServerState {
+                status: Mutex::new(Status::Created(shutdown_receiver)),
+                shutdown_requests,
+                closed,
+                closed_receiver,
+            }),
+        }
+    }
+
+    /// Start the app and bind the server's sockets. The awaitable resolves
+    /// once the server is accepting connections.
+    fn start(&self, py: Python) -> PyResult<PyObject> {
+        let shutdown_receiver = {
+            let mut status = self.state.status.lock().unwrap();
+            match std::mem::replace(&mut *status, Status::Starting) {
+                Status::Created(shutdown_receiver) => shutdown_receiver,
+                other => {
+                    *status = other;
+                    return Err(PyRuntimeError::new_err("Server has already been started"));
+                }
+            }
+        };
+
+        let driver = self.driver.clone();
+        let settings = self.settings.clone();
+        let state = self.state.clone();
+        let start = async move {
+            let server = match BoundServer::bind(driver, &settings).await {
+                Ok(server) => server,
+                Err(py_err) => {
+                    state.close();
+                    return Err(py_err);
+                }
+            };
+            state.set_status(Status::Serving(Listening {
+                addresses: server.local_addrs().to_vec(),
+                #[cfg(unix)]
+                fds: server.try_clone_fds().unwrap_or_else(|io_err| {
+                    log::warn!("Could not duplicate the listening sockets: {}", io_err);
+                    Vec::new()
+                }),
+            }));
+
+            // The app owns the process, so signals are left to it
+            let shutdown_requests = ShutdownRequests::from_server(shutdown_receiver);
+            tokio::spawn(async move {
+                server
+                    .serve(shutdown_requests, || state.set_status(Status::ShuttingDown))
+                    .await;
+                state.close();
+            });
+            Ok(())
+        };
+
+        runtime_task::spawn(py, start.boxed())
+    }
+
+    /// The addresses that the server is listening at, as `(host, port)` for
+    /// TCP sockets and `(path, None)` for Unix sockets.
+    #[getter]
+    fn addresses(&self, py: Python) -> Vec<PyObject> {
+        match &*self.state.status.lock().unwrap() {
+            Status::Serving(listening) => listening
+                .addresses
+                .iter()
+                .map(|address| address_into_py(py, address))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Duplicates of the sockets that the server is listening on, as
+    /// `socket.socket` objects.
+    #[getter]
+    fn sockets(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        let status = self.state.status.lock().unwrap();
+        let listening = match &*status {
+            Status::Serving(listening) => listening,
+            _ => return Ok(Vec::new()),
+        };
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::IntoRawFd;
+
+            let socket_class = py.import("socket")?.getattr("socket")?;
+            listening
+                .fds
+                .iter()
+                .map(|fd| {
+                    let fd = fd.try_clone()?.into_raw_fd();
+                    let kwargs = [("fileno", fd)].into_py_dict(py);
+                    Ok(socket_class.call((), Some(kwargs))?.into())
+                })
+                .collect()
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (py, listening);
+            Ok(Vec::new())
+        }
+    }
+
+    /// Whether the server is accepting connections.
+    #[getter]
+    fn is_serving(&self) -> bool {
+        matches!(&*self.state.status.lock().unwrap(), Status::Serving(_))
+    }
+
+    /// Stop accepting connections, and wait for in-flight requests to finish
+    /// for up to `timeout` seconds (or the `shutdown_timeout` setting)
+    /// before closing them. Shutting down again closes them immediately.
+    #[args(timeout = "None")]
+    fn shutdown(&self, py: Python, timeout: Option<f64>) -> PyResult<PyObject> {
+        let timeout = timeout.map(shutdown_timeout).transpose()?;
+        let started = !matches!(&*self.state.status.lock().unwrap(), Status::Created(_));
+        if started {
+            // The server has already closed if this fails
+            self.state
+                .shutdown_requests
+                .unbounded_send(ShutdownRequest { timeout })
+                .unwrap_or(());
+        }
+        self.wait_closed(py)
+    }
+
+    /// Wait until the server has shut down, and its app has finished its
+    /// lifespan shutdown.
+    fn wait_closed(&self, py: Python) -> PyResult<PyObject> {
+        if matches!(&*self.state.status.lock().unwrap(), Status::Created(_)) {
+            return runtime_task::spawn(py, future::ok(()).boxed());
+        }
+
+        let mut closed = self.state.closed_receiver.clone();
+        let wait_closed = async move {
+            while !*closed.borrow() {
+                if closed.changed().await.is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        };
+        runtime_task::spawn(py, wait_closed.boxed())
+    }
+}
+
+impl ServerState {
+    fn set_status(&self, status: Status) {
+        *self.status.lock().unwrap() = status;
+    }
+
+    fn close(&self) {
+        self.set_status(Status::Closed);
+        self.closed.send(true).unwrap_or(());
+    }
+}
+
+fn address_into_py(py: Python, address: &Address) -> PyObject {
+    match address {
+        Address::Inet(addr) => (addr.ip().to_string(), addr.port()).into_py(py),
+        Address::Unix(path) => {
+            let path = path
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            PyTuple::new(py, &[path.into_py(py), py.None()]).into()
+        }
+    }
+}
@@ -0,0 +1,218 @@
+#[cfg(unix)]
+use std::os::unix::io::OwnedFd;
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use hyper::server::accept::Accept;
+use tokio::time::Sleep;
+
+use super::{
+    address::Address, connection::Connection, limits::ConnectionLimit, listener::Listener,
+    proxy_protocol, stream::Stream, timeouts::TimeoutConfig, tls::TlsAcceptor,
+};
+
+/// How long to stop accepting after an error that isn't specific to one
+/// connection, such as running out of file descriptors.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The stream of connections accepted by the server, from any of its
+/// listeners.
+///
+/// PROXY protocol headers are read and TLS handshakes run concurrently, so
+/// a slow client doesn't hold up connections accepted after it.
+pub struct Incoming {
+    listeners: Vec<Listener>,
+    /// The listener to poll first, which rotates so that a busy listener
+    /// can't starve the others.
+    next_listener: usize,
+    tls_acceptor: Option<TlsAcceptor>,
+    /// Whether connections start with a PROXY protocol header.
+    proxy_protocol: bool,
+    timeouts: TimeoutConfig,
+    connection_limit: Option<ConnectionLimit>,
+    handshakes: FuturesUnordered<BoxFuture<'static, io::Result<Connection>>>,
+    backoff: Option<Pin<Box<Sleep>>>,
+}
+
+impl Incoming {
+    pub fn new(
+        listeners: Vec<Listener>,
+        tls_acceptor: Option<TlsAcceptor>,
+        proxy_protocol: bool,
+        timeouts: TimeoutConfig,
+        connection_limit: Option<ConnectionLimit>,
+    ) -> Self {
+        Incoming {
+            listeners,
+            next_listener: 0,
+            tls_acceptor,
+            proxy_protocol,
+            timeouts,
+            connection_limit,
+            handshakes: FuturesUnordered::new(),
+            backoff: None,
+        }
+    }
+
+    pub fn local_addrs(&self) -> io::Result<Vec<Address>> {
+        self.listeners.iter().map(Listener::local_addr).collect()
+    }
+
+    /// Duplicates of the listening sockets.
+    #[cfg(unix)]
+    pub fn try_clone_fds(&self) -> io::Result<Vec<OwnedFd>> {
+        self.listeners.iter().map(Listener::try_clone_fd).collect()
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.tls_acceptor.is_some()
+    }
+
+    /// Whether there is room for another connection under the connection
+    /// limit.
+    fn poll_room(&mut self, cx: &mut Context<'_>) -> bool {
+        match &mut self.connection_limit {
+            Some(connection_limit) => connection_limit.poll_ready(cx).is_ready(),
+            None => true,
+        }
+    }
+
+    /// Accepts from one listener until it has nothing more to give, or a
+    /// connection is ready to hand to the server.
+    fn poll_listener(&mut self, index: usize, cx: &mut Context<'_>) -> Option<Connection> {
+        while self.backoff.is_none() && self.poll_room(cx) {
+            match self.listeners[index].poll_accept(cx) {
+                Poll::Ready(Ok((stream, remote_addr, server_addr))) => {
+                    if let Some(connection) = self.accept(stream, remote_addr, server_addr) {
+                        return Some(connection);
+                    }
+                }
+                Poll::Ready(Err(io_err)) if is_connection_error(&io_err) => {
+                    log::debug!("Failed to accept connection: {}", io_err)
+                }
+                Poll::Ready(Err(io_err)) => {
+                    log::error!("Failed to accept connection: {}", io_err);
+                    let mut backoff = Box::pin(tokio::time::sleep(ACCEPT_ERROR_BACKOFF));
+                    if backoff.poll_unpin(cx).is_pending() {
+                        self.backoff = Some(backoff);
+                    }
+                }
+                Poll::Pending => break,
+            }
+        }
+        None
+    }
+
+    fn accept(
+        &mut self,
+        stream: Stream,
+        remote_addr: Address,
+        server_addr: Address,
+    ) -> Option<Connection> {
+        let slot = self
+            .connection_limit
+            .as_mut()
+            .and_then(ConnectionLimit::take);
+        if self.tls_acceptor.is_none() && !self.proxy_protocol {
+            return Some(
+                Connection::plain(stream, remote_addr, server_addr)
+                    .with_timeouts(&self.timeouts)
+                    .with_slot(slot),
+            );
+        }
+
+        let tls_acceptor = self.tls_acceptor.clone();
+        let proxy_protocol = self.proxy_protocol;
+        let timeouts = self.timeouts.clone();
+        self.handshakes.push(
+            async move {
+                let handshake = async {
+                    let (stream, proxy_header) = if proxy_protocol {
+                        let (stream, proxy_header) = proxy_protocol::accept(stream).await?;
+                        (stream, Some(proxy_header))
+                    } else {
+                        (stream, None)
+                    };
+                    let connection = match tls_acceptor {
+                        Some(tls_acceptor) => {
+                            let (stream, tls_info) = tls_acceptor.accept(stream).await?;
+                            Connection::tls(stream, tls_info, remote_addr, server_addr)
+                        }
+                        None => Connection::plain(stream, remote_addr, server_addr),
+                    };
+                    io::Result::Ok(connection.with_proxy_header(proxy_header))
+                };
+                // Slow handshakes are limited like slow request headers are.
+                let connection = match timeouts.header_read {
+                    Some(timeout) => {
+                        tokio::time::timeout(timeout, handshake)
+                            .await
+                            .map_err(|_| {
+                                io::Error::new(io::ErrorKind::TimedOut, "handshake timed out")
+                            })??
+                    }
+                    None => handshake.await?,
+                };
+                Ok(connection.with_timeouts(&timeouts).with_slot(slot))
+            }
+            .boxed(),
+        );
+        None
+    }
+}
+
+impl Accept for Incoming {
+    type Conn = Connection;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let incoming = self.get_mut();
+
+        if let Some(backoff) = &mut incoming.backoff {
+            if backoff.poll_unpin(cx).is_ready() {
+                incoming.backoff = None;
+            }
+        }
+
+        let count = incoming.listeners.len();
+        for offset in 0..count {
+            if incoming.backoff.is_some() {
+                break;
+            }
+            let index = (incoming.next_listener + offset) % count;
+            if let Some(connection) = incoming.poll_listener(index, cx) {
+                incoming.next_listener = (index + 1) % count;
+                return Poll::Ready(Some(Ok(connection)));
+            }
+        }
+
+        while let Poll::Ready(Some(handshake)) = incoming.handshakes.poll_next_unpin(cx) {
+            match handshake {
+                Ok(connection) => return Poll::Ready(Some(Ok(connection))),
+                Err(io_err) => log::debug!("Handshake failed: {}", io_err),
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Errors that only affect the connection being accepted, rather than the
+/// listener itself.
+fn is_connection_error(io_err: &io::Error) -> bool {
+    matches!(
+        io_err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::NotConnected
+    )
+}
@@ -0,0 +1,200 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
+
+/// How many seconds overloaded clients are asked to wait before retrying.
+pub const RETRY_AFTER_SECONDS: u64 = 1;
+
+/// Limits on how much work a server process takes on at once.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LimitConfig {
+    /// How many connections can be open at once. Further connections wait
+    /// in the listening socket's backlog.
+    pub max_connections: Option<usize>,
+    /// How many requests can be handled at once. Further requests are
+    /// answered with 503 Service Unavailable.
+    pub max_concurrent_requests: Option<usize>,
+    /// How long a request waits for another to finish, when there are
+    /// already as many as `max_concurrent_requests`.
+    pub request_queue_timeout: Option<Duration>,
+}
+
+/// Stops connections from being accepted while there are too many open.
+pub struct ConnectionLimit {
+    max_connections: usize,
+    semaphore: Arc<Semaphore>,
+    acquiring: Option<BoxFuture<'static, Result<OwnedSemaphorePermit, AcquireError>>>,
+    permit: Option<OwnedSemaphorePermit>,
+    /// Whether the limit was reached without there being room since, so
+    /// that a warning is only logged when the server becomes full.
+    full: bool,
+}
+
+impl ConnectionLimit {
+    pub fn new(max_connections: usize) -> Self {
+        ConnectionLimit {
+            max_connections,
+            semaphore: Arc::new(Semaphore::new(max_connections)),
+            acquiring: None,
+            permit: None,
+            full: false,
+        }
+    }
+
+    /// Whether another connection can be accepted. The task is woken once
+    /// one can, if it can't yet.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.permit.is_some() {
+            return Poll::Ready(());
+        }
+        if self.acquiring.is_none() {
+            match self.semaphore.clone().try_acquire_owned() {
+                Ok(permit) => {
+                    self.permit = Some(permit);
+                    self.full = false;
+                    return Poll::Ready(());
+                }
+                Err(_) => {
+                    if !self.full {
+                        log::warn!(
+                            "Reached the limit of {} connections; waiting for one to close",
+                            self.max_connections
+                        );
+                        self.full = true;
+                    }
+                    self.acquiring = Some(self.semaphore.clone().acquire_owned().boxed());
+                }
+            }
+        }
+
+        let acquiring = self.acquiring.as_mut().unwrap();
+        let permit = futures::ready!(acquiring.poll_unpin(cx));
+        self.acquiring = None;
+        self.permit = permit.ok();
+        Poll::Ready(())
+    }
+
+    /// Take the place for a connection which `poll_ready` found. It is given
+    /// back when the permit is dropped.
+    pub fn take(&mut self) -> Option<OwnedSemaphorePermit> {
+        self.permit.take()
+    }
+}
+
+/// Limits how many requests are handled at once, so that a spike in
+/// traffic can't pile up on the interpreter.
+#[derive(Clone)]
+pub struct RequestLimit {
+    max_concurrent_requests: usize,
+    queue_timeout: Option<Duration>,
+    semaphore: Arc<Semaphore>,
+    /// Whether the last request was turned away, so that a warning is only
+    /// logged when the server becomes overloaded.
+    overloaded: Arc<AtomicBool>,
+}
+
+impl RequestLimit {
+    pub fn new(max_concurrent_requests: usize, queue_timeout: Option<Duration>) -> Self {
+        RequestLimit {
+            max_concurrent_requests,
+            queue_timeout,
+            semaphore: Arc::new(Semaphore::new(max_concurrent_requests)),
+            overloaded: Arc::default(),
+        }
+    }
+
+    /// Wait for a place to handle a request, for up to the queue timeout.
+    /// Nothing is returned if the server is still overloaded by then.
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        let permit = match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => match self.queue_timeout {
+                Some(queue_timeout) => {
+                    tokio::time::timeout(queue_timeout, self.semaphore.clone().acquire_owned())
+                        .await
+                        .ok()
+                        .and_then(Result::ok)
+                }
+                None => None,
+            },
+        };
+
+        if permit.is_some() {
+            self.overloaded.store(false, Ordering::Relaxed);
+        } else if !self.overloaded.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "Reached the limit of {} concurrent requests; answering 503 until fewer are in progress",
+                self.max_concurrent_requests
+            );
+        }
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::task::noop_waker_ref;
+    use tokio::time::Instant;
+
+    use super::*;
+
+    const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test(start_paused = true)]
+    async fn requests_past_the_limit_are_turned_away() {
+        let limit = RequestLimit::new(2, None);
+        let _first = limit.acquire().await.unwrap();
+        let second = limit.acquire().await.unwrap();
+        assert!(limit.acquire().await.is_none());
+
+        drop(second);
+        assert!(limit.acquire().await.is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queued_requests_are_turned_away_after_the_queue_timeout() {
+        let limit = RequestLimit::new(1, Some(QUEUE_TIMEOUT));
+        let _permit = limit.acquire().await.unwrap();
+
+        let started = Instant::now();
+        assert!(limit.acquire().await.is_none());
+        assert_eq!(started.elapsed(), QUEUE_TIMEOUT);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queued_requests_go_ahead_once_a_permit_is_dropped() {
+        let limit = RequestLimit::new(1, Some(QUEUE_TIMEOUT));
+        let permit = limit.acquire().await.unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            drop(permit);
+        });
+
+        let started = Instant::now();
+        assert!(limit.acquire().await.is_some());
+        assert_eq!(started.elapsed(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn connections_wait_until_one_closes() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut limit = ConnectionLimit::new(1);
+        assert!(limit.poll_ready(&mut cx).is_ready());
+        let permit = limit.take().unwrap();
+
+        assert!(limit.poll_ready(&mut cx).is_pending());
+        assert!(limit.take().is_none());
+
+        drop(permit);
+        assert!(limit.poll_ready(&mut cx).is_ready());
+        assert!(limit.take().is_some());
+    }
+}
@@ -0,0 +1,551 @@
+use std::{
+    fmt, io,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    task::{Context, Poll},
+};
+
+use pyo3::{exceptions::PyValueError, PyErr};
+use socket2::{Domain, SockRef, Socket, Type};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use super::{address::Address, stream::Stream};
+
+/// How many connections may wait to be accepted on a TCP socket.
+const TCP_BACKLOG: i32 = 1024;
+
+/// The first file descriptor passed by systemd socket activation.
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// Options for the Unix domain sockets the server creates, as configured in
+/// `Settings`.
+#[derive(Clone, Debug, Default)]
+pub struct UnixSocketConfig {
+    /// Permissions for the socket file, such as `0o660`.
+    pub mode: Option<u32>,
+    /// The user id that should own the socket file.
+    pub owner: Option<u32>,
+    /// The group id that should own the socket file.
+    pub group: Option<u32>,
+}
+
+/// A socket for the server to listen on, as configured in `Settings`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BindTarget {
+    /// A host, which may be a name or an IP address, and a port.
+    Tcp(String, u16),
+    Unix(String),
+    /// A socket which was already bound, by whoever started the server.
+    Fd(i32),
+    /// The sockets passed by systemd, with the `LISTEN_FDS` protocol.
+    SocketActivation,
+}
+
+impl FromStr for BindTarget {
+    type Err = PyErr;
+
+    /// Parse a bind target in the form `HOST:PORT`, `[IPV6]:PORT`,
+    /// `unix:PATH` or `fd://FD`. IPv6 addresses need their brackets, as
+    /// `::1:8000` would itself be a valid address.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            PyValueError::new_err(format!(
+                "'{}' is not a valid bind target: expected 'HOST:PORT', '[IPV6]:PORT', 'unix:PATH' or 'fd://FD'",
+                value
+            ))
+        };
+
+        if let Some(path) = value.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(invalid());
+            }
+            return Ok(BindTarget::Unix(path.to_string()));
+        }
+        if let Some(fd) = value.strip_prefix("fd://") {
+            return match fd.parse() {
+                Ok(fd) if fd >= 0 => Ok(BindTarget::Fd(fd)),
+                _ => Err(invalid()),
+            };
+        }
+
+        let (host, port) = value.rsplit_once(':').ok_or_else(invalid)?;
+        let host = match host.strip_prefix('[') {
+            Some(host) => {
+                let host = host.strip_suffix(']').ok_or_else(invalid)?;
+                // The address may have a zone, as in `fe80::1%eth0`
+                let (address, _zone) = host.split_once('%').unwrap_or((host, ""));
+                address.parse::<Ipv6Addr>().map_err(|_| invalid())?;
+                host
+            }
+            None if host.contains(':') => return Err(invalid()),
+            None => host,
+        };
+        let port = port.parse().map_err(|_| invalid())?;
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(BindTarget::Tcp(host.to_string(), port))
+    }
+}
+
+impl fmt::Display for BindTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindTarget::Tcp(host, port) if host.contains(':') => write!(f, "[{}]:{}", host, port),
+            BindTarget::Tcp(host, port) => write!(f, "{}:{}", host, port),
+            BindTarget::Unix(path) => write!(f, "unix:{}", path),
+            BindTarget::Fd(fd) => write!(f, "fd://{}", fd),
+            BindTarget::SocketActivation => write!(f, "systemd sockets"),
+        }
+    }
+}
+
+/// Everything that the server listens on, as configured in `Settings`.
+#[derive(Clone, Debug)]
+pub struct BindConfig {
+    pub targets: Vec<BindTarget>,
+    pub uds: UnixSocketConfig,
+}
+
+impl BindConfig {
+    /// Resolve every target and bind to all of the resulting addresses.
+    pub async fn bind(&self) -> io::Result<Vec<Listener>> {
+        let mut resolved = Vec::new();
+        for target in &self.targets {
+            for resolved_target in resolve(target)
+                .await
+                .map_err(|io_err| with_target(target, io_err))?
+            {
+                if !resolved.iter().any(|(_, other)| *other == resolved_target) {
+                    resolved.push((target, resolved_target));
+                }
+            }
+        }
+
+        // An IPv6 wildcard socket normally accepts IPv4 connections too, which
+        // would clash with any IPv4 socket on the same port.
+        let ipv4_ports = resolved
+            .iter()
+            .filter_map(|(_, resolved_target)| match resolved_target {
+                ResolvedTarget::Tcp(SocketAddr::V4(addr)) => Some(addr.port()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut listeners = Vec::new();
+        let mut bound_targets = Vec::new();
+        for (target, resolved_target) in resolved {
+            let bound = match resolved_target {
+                ResolvedTarget::Tcp(addr) => {
+                    let only_v6 = addr.is_ipv6() && ipv4_ports.contains(&addr.port());
+                    Listener::bind_tcp(addr, only_v6)
+                }
+                ResolvedTarget::Unix(path) => Listener::bind_unix(path, &self.uds),
+                ResolvedTarget::Fd(fd) => Listener::from_fd(fd),
+            };
+
+            match bound {
+                Ok(listener) => {
+                    listeners.push(listener);
+                    bound_targets.push(target);
+                }
+                // Hostnames can resolve to addresses this machine can't bind,
+                // such as `::1` for `localhost` where IPv6 is disabled.
+                Err(io_err) if is_unavailable(&io_err) && is_hostname(target) => {
+                    log::warn!("Skipping an address of {}: {}", target, io_err)
+                }
+                Err(io_err) => return Err(with_target(target, io_err)),
+            }
+        }
+
+        match self
+            .targets
+            .iter()
+            .find(|target| !bound_targets.contains(target))
+        {
+            Some(target) => Err(with_target(
+                target,
+                io::Error::new(io::ErrorKind::AddrNotAvailable, "No address could be bound"),
+            )),
+            None => Ok(listeners),
+        }
+    }
+}
+
+/// A bind target, narrowed down to a single socket.
+#[derive(PartialEq)]
+enum ResolvedTarget<'a> {
+    Tcp(SocketAddr),
+    Unix(&'a str),
+    Fd(i32),
+}
+
+async fn resolve(target: &BindTarget) -> io::Result<Vec<ResolvedTarget<'_>>> {
+    Ok(match target {
+        BindTarget::Tcp(host, port) => tokio::net::lookup_host((host.as_str(), *port))
+            .await?
+            .map(ResolvedTarget::Tcp)
+            .collect(),
+        BindTarget::Unix(path) => vec![ResolvedTarget::Unix(path)],
+        BindTarget::Fd(fd) => vec![ResolvedTarget::Fd(*fd)],
+        BindTarget::SocketActivation => {
+            systemd_fds()?.into_iter().map(ResolvedTarget::Fd).collect()
+        }
+    })
+}
+
+fn is_hostname(target: &BindTarget) -> bool {
+    match target {
+        BindTarget::Tcp(host, _) => host.parse::<IpAddr>().is_err(),
+        _ => false,
+    }
+}
+
+fn is_unavailable(io_err: &io::Error) -> bool {
+    matches!(
+        io_err.kind(),
+        io::ErrorKind::AddrNotAvailable | io::ErrorKind::Unsupported
+    )
+}
+
+fn with_target(target: &BindTarget, io_err: io::Error) -> io::Error {
+    io::Error::new(io_err.kind(), format!("{}: {}", target, io_err))
+}
+
+/// A socket which the server accepts connections on.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix {
+        listener: UnixListener,
+        path: Option<PathBuf>,
+        /// Whether the socket file was created by the server, in which case
+        /// it is removed again when the listener is dropped.
+        remove_on_drop: bool,
+    },
+}
+
+impl Listener {
+    fn bind_tcp(addr: SocketAddr, only_v6: bool) -> io::Result<Self> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        if only_v6 {
+            socket.set_only_v6(true)?;
+        }
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(TCP_BACKLOG)?;
+        socket.set_nonblocking(true)?;
+        Ok(Listener::Tcp(TcpListener::from_std(socket.into())?))
+    }
+
+    /// Create a Unix domain socket, replacing any stale socket file left
+    /// behind at the same path.
+    #[cfg(unix)]
+    fn bind_unix(path: &str, config: &UnixSocketConfig) -> io::Result<Self> {
+        use std::os::unix::fs::{chown, PermissionsExt};
+
+        let path = PathBuf::from(path);
+        remove_stale_socket(&path)?;
+
+        // Constructed first, so that the socket file is removed on errors
+        let listener = Listener::Unix {
+            listener: UnixListener::bind(&path)?,
+            path: Some(path.clone()),
+            remove_on_drop: true,
+        };
+
+        if let Some(mode) = config.mode {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+        }
+        if config.owner.is_some() || config.group.is_some() {
+            chown(&path, config.owner, config.group)?;
+        }
+
+        Ok(listener)
+    }
+
+    /// Listen on a socket that is already bound. The file descriptor is
+    /// duplicated, so the caller is free to close its own.
+    #[cfg(unix)]
+    fn from_fd(fd: i32) -> io::Result<Self> {
+        use std::os::unix::io::{BorrowedFd, OwnedFd};
+
+        if fd < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a valid file descriptor", fd),
+            ));
+        }
+        // SAFETY: the descriptor is only borrowed long enough to duplicate it
+        let owned_fd = unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?;
+        // A socket that is bound but not listening yet would otherwise only
+        // fail once connections are accepted. Listening again just sets the
+        // backlog of a socket that already is.
+        SockRef::from(&owned_fd).listen(TCP_BACKLOG)?;
+
+        let std_listener = std::net::TcpListener::from(owned_fd);
+        // Only sockets of the inet families have an inet address
+        if std_listener.local_addr().is_ok() {
+            std_listener.set_nonblocking(true)?;
+            return Ok(Listener::Tcp(TcpListener::from_std(std_listener)?));
+        }
+
+        let std_listener = std::os::unix::net::UnixListener::from(OwnedFd::from(std_listener));
+        let local_addr = std_listener.local_addr()?;
+        std_listener.set_nonblocking(true)?;
+        Ok(Listener::Unix {
+            listener: UnixListener::from_std(std_listener)?,
+            path: local_addr.as_pathname().map(PathBuf::from),
+            remove_on_drop: false,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn bind_unix(_path: &str, _config: &UnixSocketConfig) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix domain sockets are not supported on this platform",
+        ))
+    }
+
+    #[cfg(not(unix))]
+    fn from_fd(_fd: i32) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Listening on file descriptors is not supported on this platform",
+        ))
+    }
+
+    pub fn local_addr(&self) -> io::Result<Address> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().map(Address::Inet),
+            #[cfg(unix)]
+            Listener::Unix { path, .. } => Ok(Address::Unix(path.clone())),
+        }
+    }
+
+    /// A duplicate of the listening socket.
+    #[cfg(unix)]
+    pub fn try_clone_fd(&self) -> io::Result<std::os::unix::io::OwnedFd> {
+        use std::os::unix::io::{AsRawFd, BorrowedFd};
+
+        let fd = match self {
+            Listener::Tcp(listener) => listener.as_raw_fd(),
+            Listener::Unix { listener, .. } => listener.as_raw_fd(),
+        };
+        // SAFETY: the listener owns the descriptor, and keeps it open while it is duplicated
+        unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()
+    }
+
+    /// Accept a connection, returning its stream along with the addresses
+    /// of the client and server ends.
+    pub fn poll_accept(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(Stream, Address, Address)>> {
+        match self {
+            Listener::Tcp(listener) => listener.poll_accept(cx).map(|accepted| {
+                let (stream, remote_addr) = accepted?;
+                let server_addr = stream.local_addr()?;
+                Ok((
+                    Stream::Tcp(stream),
+                    Address::Inet(remote_addr),
+                    Address::Inet(server_addr),
+                ))
+            }),
+            #[cfg(unix)]
+            Listener::Unix { listener, path, .. } => listener.poll_accept(cx).map(|accepted| {
+                let (stream, remote_addr) = accepted?;
+                Ok((
+                    Stream::Unix(stream),
+                    Address::Unix(remote_addr.as_pathname().map(PathBuf::from)),
+                    Address::Unix(path.clone()),
+                ))
+            }),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix {
+            path: Some(path),
+            remove_on_drop: true,
+            ..
+        } = self
+        {
+            std::fs::remove_file(path).unwrap_or(());
+        }
+    }
+}
+
+/// Remove a socket file which no server is listening on any more, such as
+/// after a crash. Fails if the socket is still in use, or the path is some
+/// other kind of file.
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(io_err) if io_err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(io_err) => return Err(io_err),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+
+    match std::os::unix::net::UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is already in use", path.display()),
+        )),
+        Err(io_err) if io_err.kind() == io::ErrorKind::ConnectionRefused => {
+            log::info!("Removing stale socket {}", path.display());
+            std::fs::remove_file(path)
+        }
+        Err(io_err) => Err(io_err),
+    }
+}
+
+/// The file descriptors passed to this process by systemd, as described in
+/// `sd_listen_fds(3)`.
+#[cfg(unix)]
+fn systemd_fds() -> io::Result<Vec<i32>> {
+    let not_activated = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "No sockets were passed by systemd (LISTEN_FDS and LISTEN_PID are not set for this process)",
+        )
+    };
+
+    let listen_pid = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .ok_or_else(not_activated)?;
+    if listen_pid != std::process::id() {
+        return Err(not_activated());
+    }
+
+    let listen_fds = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<i32>().ok())
+        .filter(|fds| *fds > 0)
+        .ok_or_else(not_activated)?;
+
+    Ok((SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + listen_fds).collect())
+}
+
+#[cfg(not(unix))]
+fn systemd_fds() -> io::Result<Vec<i32>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Socket activation is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_form_of_bind_target() {
+        let cases = [
+            ("0.0.0.0:8000", BindTarget::Tcp("0.0.0.0".to_string(), 8000)),
+            ("localhost:80", BindTarget::Tcp("localhost".to_string(), 80)),
+            ("[::]:8000", BindTarget::Tcp("::".to_string(), 8000)),
+            ("[::1]:8000", BindTarget::Tcp("::1".to_string(), 8000)),
+            ("[fe80::1%2]:0", BindTarget::Tcp("fe80::1%2".to_string(), 0)),
+            (
+                "unix:/tmp/app.sock",
+                BindTarget::Unix("/tmp/app.sock".to_string()),
+            ),
+            (
+                "unix:relative.sock",
+                BindTarget::Unix("relative.sock".to_string()),
+            ),
+            ("fd://3", BindTarget::Fd(3)),
+        ];
+        for (value, target) in cases.iter() {
+            assert_eq!(
+                value.parse::<BindTarget>().ok().as_ref(),
+                Some(target),
+                "for {}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_bind_targets() {
+        let cases = [
+            "",
+            "8000",
+            ":8000",
+            "localhost",
+            "localhost:",
+            "localhost:http",
+            "localhost:65536",
+            "::1:8000",
+            "::1",
+            "[::1]",
+            "[::1:8000",
+            "::1]:8000",
+            "[]:8000",
+            "[localhost]:8000",
+            "[127.0.0.1]:8000",
+            "unix:",
+            "fd://",
+            "fd://-1",
+            "fd://three",
+        ];
+        for value in cases.iter() {
+            assert!(
+                value.parse::<BindTarget>().is_err(),
+                "{} should be rejected",
+                value
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn listens_on_inherited_sockets_that_are_only_bound() {
+        use std::os::unix::io::AsRawFd;
+
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        socket.bind(&addr.into()).unwrap();
+        let addr = socket.local_addr().unwrap().as_socket().unwrap();
+
+        let _listener = Listener::from_fd(socket.as_raw_fd()).unwrap();
+        assert!(std::net::TcpStream::connect(addr).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn rejects_descriptors_that_are_not_sockets() {
+        use std::os::unix::io::AsRawFd;
+
+        let file = std::fs::File::open("/dev/null").unwrap();
+        assert!(Listener::from_fd(file.as_raw_fd()).is_err());
+    }
+
+    #[test]
+    fn bind_targets_display_as_they_are_parsed() {
+        for value in &["127.0.0.1:80", "[::1]:8000", "unix:/tmp/app.sock", "fd://3"] {
+            let target: BindTarget = value.parse().unwrap();
+            assert_eq!(target.to_string(), *value);
+        }
+    }
+}
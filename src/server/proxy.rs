@@ -0,0 +1,599 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use http::{header, HeaderMap};
+
+use super::{address::Address, connection::ConnectionInfo};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+const X_FORWARDED_PREFIX: &str = "x-forwarded-prefix";
+const X_SCRIPT_NAME: &str = "x-script-name";
+
+/// A peer whose forwarding headers are believed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrustedProxy {
+    /// Every peer, given as `*`.
+    Any,
+    /// Peers connected over a Unix domain socket, given as `unix`.
+    Unix,
+    /// Peers in a network, given as an address or in CIDR notation.
+    Network(IpAddr, u8),
+}
+
+impl TrustedProxy {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self, ip.to_canonical()) {
+            (TrustedProxy::Any, _) => true,
+            (TrustedProxy::Unix, _) => false,
+            (TrustedProxy::Network(IpAddr::V4(network), prefix), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(*prefix)).unwrap_or(0);
+                u32::from(*network) & mask == u32::from(ip) & mask
+            }
+            (TrustedProxy::Network(IpAddr::V6(network), prefix), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(*prefix)).unwrap_or(0);
+                u128::from(*network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid forwarded_allow_ips entry {:?}: expected '*', 'unix', an IP address or a network such as '10.0.0.0/8'",
+                s
+            )
+        };
+        match s.trim() {
+            "*" => Ok(TrustedProxy::Any),
+            "unix" => Ok(TrustedProxy::Unix),
+            network => {
+                let (addr, prefix) = match network.split_once('/') {
+                    Some((addr, prefix)) => (addr, Some(prefix)),
+                    None => (network, None),
+                };
+                let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+                let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+                let prefix = match prefix {
+                    Some(prefix) => prefix
+                        .parse()
+                        .ok()
+                        .filter(|prefix| *prefix <= max_prefix)
+                        .ok_or_else(invalid)?,
+                    None => max_prefix,
+                };
+                // Peers are compared as IPv4 if they're IPv4-mapped, so
+                // networks of them have to be as well
+                match addr {
+                    IpAddr::V6(v6) if prefix >= 96 && v6.to_ipv4_mapped().is_some() => {
+                        Ok(TrustedProxy::Network(addr.to_canonical(), prefix - 96))
+                    }
+                    _ => Ok(TrustedProxy::Network(addr, prefix)),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for TrustedProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrustedProxy::Any => write!(f, "*"),
+            TrustedProxy::Unix => write!(f, "unix"),
+            TrustedProxy::Network(addr, prefix) => write!(f, "{}/{}", addr, prefix),
+        }
+    }
+}
+
+/// The proxies that are trusted to say who their clients are.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProxyConfig {
+    pub forwarded_allow_ips: Vec<TrustedProxy>,
+    /// Whether connections start with a PROXY protocol header, which load
+    /// balancers send in place of forwarding headers.
+    pub proxy_protocol: bool,
+    /// The path that the app is mounted under, which is used unless a
+    /// trusted proxy gives one.
+    pub root_path: String,
+    /// Whether request paths already start with the root path, because the
+    /// gateway in front of the server doesn't strip it.
+    pub root_path_included: bool,
+}
+
+/// What a proxy says about the request it forwarded.
+#[derive(Debug, Default)]
+pub struct Forwarded {
+    pub client: Option<SocketAddr>,
+    pub secure: Option<bool>,
+    pub host: Option<(String, Option<u16>)>,
+    /// The path prefix that the proxy serves the app under.
+    pub root_path: Option<String>,
+    /// Whether request paths already start with the root path.
+    pub root_path_included: bool,
+}
+
+impl ProxyConfig {
+    /// The connection as its client saw it, if a trusted proxy forwarded the
+    /// request on it. `Forwarded` is read if it was sent, and the
+    /// `X-Forwarded-*` headers otherwise.
+    ///
+    /// Each proxy adds the address of its own client to the end of the
+    /// list, so the client is the last address that isn't a trusted proxy.
+    /// Otherwise only the scheme and host given by the nearest proxy are
+    /// used, as earlier ones can't be told apart from the client's own.
+    ///
+    /// The root path is taken from `X-Forwarded-Prefix` or `X-Script-Name`
+    /// if a trusted proxy sent either, and from the settings otherwise.
+    /// Either way, it's added to the start of the request's path unless the
+    /// settings say that it's already there.
+    pub fn forwarded(&self, connection: &ConnectionInfo, headers: &HeaderMap) -> ConnectionInfo {
+        let mut forwarded = if !self.is_trusted(connection.remote_addr()) {
+            Forwarded::default()
+        } else if headers.contains_key(header::FORWARDED) {
+            self.parse_forwarded(headers)
+        } else {
+            self.parse_x_forwarded(headers)
+        };
+        forwarded.root_path = forwarded.root_path.or_else(|| Some(self.root_path.clone()));
+        forwarded.root_path_included = self.root_path_included;
+        connection.forwarded(forwarded)
+    }
+
+    fn is_trusted(&self, addr: &Address) -> bool {
+        self.forwarded_allow_ips.iter().any(|proxy| match addr {
+            Address::Inet(addr) => proxy.contains(addr.ip()),
+            Address::Unix(_) => matches!(proxy, TrustedProxy::Any | TrustedProxy::Unix),
+        })
+    }
+
+    fn is_trusted_ip(&self, ip: IpAddr) -> bool {
+        self.forwarded_allow_ips
+            .iter()
+            .any(|proxy| proxy.contains(ip))
+    }
+
+    /// Parse the hops in RFC 7239 `Forwarded` headers.
+    fn parse_forwarded(&self, headers: &HeaderMap) -> Forwarded {
+        let hops: Vec<Vec<(String, String)>> = header_values(headers, header::FORWARDED.as_str())
+            .map(|element| {
+                split_quoted(element, ';')
+                    .into_iter()
+                    .filter_map(|pair| {
+                        let (name, value) = pair.split_once('=')?;
+                        Some((name.trim().to_ascii_lowercase(), unquote(value.trim())))
+                    })
+                    .collect()
+            })
+            .collect();
+        let param = |hop: &Vec<(String, String)>, name: &str| {
+            hop.iter()
+                .find(|(param, _)| param == name)
+                .map(|(_, value)| value.clone())
+        };
+
+        Forwarded {
+            client: self.client(hops.iter().filter_map(|hop| param(hop, "for"))),
+            secure: hops
+                .iter()
+                .rev()
+                .find_map(|hop| param(hop, "proto"))
+                .and_then(|proto| is_secure_proto(&proto)),
+            host: hops
+                .iter()
+                .rev()
+                .find_map(|hop| param(hop, "host"))
+                .and_then(|host| parse_host(&host)),
+            root_path: parse_prefix(headers),
+            ..Forwarded::default()
+        }
+    }
+
+    /// Parse `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`.
+    fn parse_x_forwarded(&self, headers: &HeaderMap) -> Forwarded {
+        Forwarded {
+            client: self.client(header_values(headers, X_FORWARDED_FOR).map(String::from)),
+            secure: header_values(headers, X_FORWARDED_PROTO)
+                .last()
+                .and_then(is_secure_proto),
+            host: header_values(headers, X_FORWARDED_HOST)
+                .last()
+                .and_then(parse_host),
+            root_path: parse_prefix(headers),
+            ..Forwarded::default()
+        }
+    }
+
+    /// The last of a list of forwarded-for nodes which isn't a trusted
+    /// proxy, or the first one if they all are.
+    fn client(&self, nodes: impl DoubleEndedIterator<Item = String>) -> Option<SocketAddr> {
+        let mut first = None;
+        for node in nodes.rev() {
+            let addr = parse_node(&node)?;
+            if !self.is_trusted_ip(addr.ip()) {
+                return Some(addr);
+            }
+            first = Some(addr);
+        }
+        first
+    }
+}
+
+/// The comma separated values of all the headers with a name.
+fn header_values<'a>(
+    headers: &'a HeaderMap,
+    name: &str,
+) -> impl DoubleEndedIterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| split_quoted(value, ','))
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Split a header value on a separator, except inside quoted strings.
+fn split_quoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(value[start..index].trim());
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(value[start..].trim());
+    parts
+}
+
+fn unquote(value: &str) -> String {
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        Some(quoted) => {
+            let mut unquoted = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                unquoted.extend(if c == '\\' { chars.next() } else { Some(c) });
+            }
+            unquoted
+        }
+        None => value.to_string(),
+    }
+}
+
+/// A forwarded-for node, which is an IP address that may have a port, with
+/// IPv6 addresses in brackets. Obfuscated and `unknown` nodes aren't.
+fn parse_node(node: &str) -> Option<SocketAddr> {
+    let node = node.trim();
+    node.parse::<SocketAddr>()
+        .ok()
+        .or_else(|| node.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, 0)))
+        .or_else(|| {
+            let ip = node.strip_prefix('[')?.strip_suffix(']')?;
+            ip.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, 0))
+        })
+}
+
+fn is_secure_proto(proto: &str) -> Option<bool> {
+    match proto.trim().to_ascii_lowercase().as_str() {
+        "https" | "wss" => Some(true),
+        "http" | "ws" => Some(false),
+        _ => None,
+    }
+}
+
+/// A host name or address, and the port if there is one.
+fn parse_host(host: &str) -> Option<(String, Option<u16>)> {
+    let authority: http::uri::Authority = host.trim().parse().ok()?;
+    if authority.as_str().contains('@') {
+        return None;
+    }
+    let host = authority
+        .host()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    Some((host.to_string(), authority.port_u16()))
+}
+
+/// The path prefix from `X-Forwarded-Prefix`, or from `X-Script-Name`,
+/// percent-decoded and without a trailing slash.
+fn parse_prefix(headers: &HeaderMap) -> Option<String> {
+    let prefix = header_values(headers, X_FORWARDED_PREFIX)
+        .last()
+        .or_else(|| header_values(headers, X_SCRIPT_NAME).last())?;
+    let prefix = percent_encoding::percent_decode_str(prefix)
+        .decode_utf8()
+        .ok()?;
+    if !prefix.starts_with('/') {
+        return None;
+    }
+    Some(prefix.trim_end_matches('/').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use http::HeaderValue;
+
+    const PROXY: &str = "10.0.0.1:40000";
+
+    fn network(s: &str) -> TrustedProxy {
+        s.parse().unwrap()
+    }
+
+    fn config(trusted: &[&str]) -> ProxyConfig {
+        ProxyConfig {
+            forwarded_allow_ips: trusted.iter().map(|s| network(s)).collect(),
+            ..ProxyConfig::default()
+        }
+    }
+
+    /// The connection as forwarded through a proxy at `PROXY`.
+    fn forwarded(config: &ProxyConfig, headers: &[(&str, &str)]) -> ConnectionInfo {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.append(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        let connection = ConnectionInfo::new(
+            Address::Inet(PROXY.parse().unwrap()),
+            Address::Inet("10.0.0.2:8000".parse().unwrap()),
+            None,
+        );
+        config.forwarded(&connection, &header_map)
+    }
+
+    fn client(connection: &ConnectionInfo) -> String {
+        connection.remote_addr().to_string()
+    }
+
+    #[test]
+    fn zero_prefix_contains_every_address_of_its_family() {
+        assert!(network("0.0.0.0/0").contains("203.0.113.9".parse().unwrap()));
+        assert!(network("::/0").contains("2001:db8::1".parse().unwrap()));
+        assert!(!network("0.0.0.0/0").contains("2001:db8::1".parse().unwrap()));
+        assert!(!network("::/0").contains("203.0.113.9".parse().unwrap()));
+    }
+
+    #[test]
+    fn full_prefix_contains_only_the_address() {
+        assert!(network("192.0.2.1/32").contains("192.0.2.1".parse().unwrap()));
+        assert!(!network("192.0.2.1/32").contains("192.0.2.2".parse().unwrap()));
+        assert!(network("2001:db8::1/128").contains("2001:db8::1".parse().unwrap()));
+        assert!(!network("2001:db8::1/128").contains("2001:db8::2".parse().unwrap()));
+        assert_eq!(network("192.0.2.1"), network("192.0.2.1/32"));
+        assert_eq!(network("2001:db8::1"), network("2001:db8::1/128"));
+    }
+
+    #[test]
+    fn networks_contain_their_addresses() {
+        assert!(network("10.0.0.0/8").contains("10.255.0.1".parse().unwrap()));
+        assert!(!network("10.0.0.0/8").contains("11.0.0.1".parse().unwrap()));
+        assert!(network("2001:db8::/32").contains("2001:db8:ffff::1".parse().unwrap()));
+        assert!(!network("2001:db8::/32").contains("2001:db9::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_are_ipv4() {
+        assert!(network("10.0.0.0/8").contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(network("::ffff:10.0.0.1").contains("10.0.0.1".parse().unwrap()));
+        assert!(network("::ffff:10.0.0.0/104").contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert_eq!(network("::ffff:10.0.0.0/104"), network("10.0.0.0/8"));
+    }
+
+    #[test]
+    fn invalid_networks_are_rejected() {
+        for s in &[
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/",
+            "10.0.0/8",
+            "localhost",
+        ] {
+            assert!(s.parse::<TrustedProxy>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn untrusted_peers_are_not_believed() {
+        let config = config(&["192.0.2.0/24"]);
+        let connection = forwarded(
+            &config,
+            &[
+                ("x-forwarded-for", "203.0.113.9"),
+                ("x-forwarded-proto", "https"),
+                ("x-forwarded-host", "example.com"),
+            ],
+        );
+
+        assert_eq!(client(&connection), PROXY);
+        assert!(!connection.is_secure());
+        assert_eq!(connection.forwarded_host(), None);
+    }
+
+    #[test]
+    fn client_is_the_last_untrusted_address() {
+        let config = config(&["10.0.0.0/8"]);
+        let connection = forwarded(
+            &config,
+            &[("x-forwarded-for", "198.51.100.1, 203.0.113.9, 10.0.0.5")],
+        );
+
+        assert_eq!(client(&connection), "203.0.113.9:0");
+    }
+
+    #[test]
+    fn spoofed_leftmost_addresses_are_ignored() {
+        let config = config(&["10.0.0.0/8"]);
+        let connection = forwarded(
+            &config,
+            &[
+                ("x-forwarded-for", "10.0.0.7, 127.0.0.1"),
+                ("x-forwarded-for", "203.0.113.9"),
+            ],
+        );
+
+        assert_eq!(client(&connection), "203.0.113.9:0");
+    }
+
+    #[test]
+    fn client_is_the_first_address_if_all_are_trusted() {
+        let config = config(&["10.0.0.0/8"]);
+        let connection = forwarded(&config, &[("x-forwarded-for", "10.0.0.7, 10.0.0.5")]);
+
+        assert_eq!(client(&connection), "10.0.0.7:0");
+    }
+
+    #[test]
+    fn forwarded_is_preferred_to_x_forwarded() {
+        let config = config(&["10.0.0.0/8"]);
+        let connection = forwarded(
+            &config,
+            &[
+                ("forwarded", "for=203.0.113.9;proto=https"),
+                ("x-forwarded-for", "198.51.100.1"),
+                ("x-forwarded-proto", "http"),
+            ],
+        );
+
+        assert_eq!(client(&connection), "203.0.113.9:0");
+        assert!(connection.is_secure());
+    }
+
+    #[test]
+    fn quoted_forwarded_values_can_contain_separators() {
+        let config = config(&["10.0.0.0/8"]);
+        let connection = forwarded(
+            &config,
+            &[(
+                "forwarded",
+                r#"for="[2001:db8::1]:4711";host="a,b;c\"d";proto=https, for=10.0.0.5"#,
+            )],
+        );
+
+        assert_eq!(client(&connection), "[2001:db8::1]:4711");
+        assert!(connection.is_secure());
+        // The nearest hop didn't give a host, and the earlier one isn't valid
+        assert_eq!(connection.forwarded_host(), None);
+
+        assert_eq!(
+            split_quoted(r#"for="a,b", for=c"#, ','),
+            vec![r#"for="a,b""#, "for=c"]
+        );
+        assert_eq!(
+            split_quoted(r#"host="x;y";proto=http"#, ';'),
+            vec![r#"host="x;y""#, "proto=http"]
+        );
+        assert_eq!(unquote(r#""a\"b\\c""#), r#"a"b\c"#);
+    }
+
+    #[test]
+    fn forwarded_host_is_from_the_nearest_hop() {
+        let config = config(&["10.0.0.0/8"]);
+        let connection = forwarded(
+            &config,
+            &[(
+                "forwarded",
+                "for=203.0.113.9;host=evil.example, for=10.0.0.5;host=\"example.com:8443\"",
+            )],
+        );
+
+        assert_eq!(
+            connection.forwarded_host(),
+            Some(&("example.com".to_string(), 8443))
+        );
+    }
+
+    #[test]
+    fn unknown_and_obfuscated_nodes_are_not_addresses() {
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(parse_node("_hidden"), None);
+        assert_eq!(parse_node("\"[2001:db8::1]\""), None);
+        assert_eq!(parse_node("[2001:db8::1]"), "[2001:db8::1]:0".parse().ok());
+        assert_eq!(parse_node("192.0.2.1:80"), "192.0.2.1:80".parse().ok());
+    }
+
+    #[test]
+    fn unknown_client_leaves_the_peer_address() {
+        let config = config(&["10.0.0.0/8"]);
+
+        let connection = forwarded(&config, &[("forwarded", "for=unknown")]);
+        assert_eq!(client(&connection), PROXY);
+
+        let connection = forwarded(&config, &[("forwarded", "for=_hidden;proto=https")]);
+        assert_eq!(client(&connection), PROXY);
+        assert!(connection.is_secure());
+    }
+
+    #[test]
+    fn unknown_nodes_behind_the_client_are_skipped() {
+        let config = config(&["10.0.0.0/8"]);
+        let connection = forwarded(
+            &config,
+            &[("forwarded", "for=unknown, for=203.0.113.9, for=10.0.0.5")],
+        );
+
+        assert_eq!(client(&connection), "203.0.113.9:0");
+    }
+
+    #[test]
+    fn hosts_with_user_info_are_rejected() {
+        assert_eq!(parse_host("user@example.com"), None);
+        assert_eq!(parse_host("user:pass@example.com:8080"), None);
+        assert_eq!(
+            parse_host("example.com:8080"),
+            Some(("example.com".to_string(), Some(8080)))
+        );
+        assert_eq!(
+            parse_host("[2001:db8::1]"),
+            Some(("2001:db8::1".to_string(), None))
+        );
+
+        let config = config(&["10.0.0.0/8"]);
+        let connection = forwarded(&config, &[("x-forwarded-host", "evil@example.com")]);
+        assert_eq!(connection.forwarded_host(), None);
+    }
+
+    #[test]
+    fn root_path_is_from_a_trusted_prefix_header_or_the_settings() {
+        let config = ProxyConfig {
+            root_path: "/app".to_string(),
+            ..config(&["10.0.0.0/8"])
+        };
+
+        let connection = forwarded(&config, &[]);
+        assert_eq!(connection.root_path(), "/app");
+
+        let connection = forwarded(&config, &[("x-forwarded-prefix", "/my%20app/")]);
+        assert_eq!(connection.root_path(), "/my app");
+
+        let connection = forwarded(&config, &[("x-script-name", "/script")]);
+        assert_eq!(connection.root_path(), "/script");
+
+        let connection = forwarded(&config, &[("x-forwarded-prefix", "relative")]);
+        assert_eq!(connection.root_path(), "/app");
+    }
+}
@@ -0,0 +1,522 @@
+use std::{
+    convert::TryInto,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::AsyncReadExt;
+
+use super::stream::Stream;
+
+/// The signature that starts a version 2 header.
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The start of a version 1 header.
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// The longest a version 1 header can be, including its line ending.
+const V1_MAX_LENGTH: usize = 107;
+
+/// The length of a version 2 header before its addresses.
+const V2_HEADER_LENGTH: usize = 16;
+
+/// The TLV saying whether the client connected to the proxy with TLS.
+const PP2_TYPE_SSL: u8 = 0x20;
+
+/// The flag in `PP2_TYPE_SSL` set when the client connected with TLS.
+const PP2_CLIENT_SSL: u8 = 0x01;
+
+/// What a load balancer says about the connection it passed on, in a PROXY
+/// protocol header.
+#[derive(Debug, PartialEq)]
+pub struct ProxyHeader {
+    pub version: u8,
+    /// The addresses of the client and of the server it connected to, which
+    /// are missing for health checks and connections that aren't over TCP.
+    pub source: Option<SocketAddr>,
+    pub destination: Option<SocketAddr>,
+    /// The type and value of each TLV in a version 2 header.
+    pub tlvs: Vec<(u8, Bytes)>,
+}
+
+impl ProxyHeader {
+    /// Whether the client connected to the proxy with TLS, if it says.
+    pub fn is_secure(&self) -> bool {
+        self.tlvs.iter().any(|(kind, value)| {
+            *kind == PP2_TYPE_SSL
+                && matches!(value.first(), Some(client) if client & PP2_CLIENT_SSL != 0)
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Parsed {
+    /// More of the connection needs to be read to tell.
+    Incomplete,
+    /// The connection doesn't start with a header.
+    Missing,
+    /// A header, and how many bytes it took.
+    Header(ProxyHeader, usize),
+}
+
+/// Read the PROXY protocol header at the start of a connection, and give
+/// back the stream to be read from where it ends.
+///
+/// A header is required, as the spec asks, rather than read only if it is
+/// there: otherwise clients connecting directly could send one to say they
+/// are anyone they like.
+pub async fn accept(mut stream: Stream) -> io::Result<(Stream, ProxyHeader)> {
+    let mut buf = BytesMut::with_capacity(V1_MAX_LENGTH);
+    loop {
+        match parse(&buf).map_err(invalid)? {
+            Parsed::Incomplete => {
+                if stream.read_buf(&mut buf).await? == 0 {
+                    return Err(invalid("the connection closed before it was complete"));
+                }
+            }
+            Parsed::Missing => return Err(invalid("the connection didn't start with one")),
+            Parsed::Header(header, length) => {
+                buf.advance(length);
+                return Ok((Stream::rewound(buf.freeze(), stream), header));
+            }
+        }
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid PROXY protocol header: {}", reason),
+    )
+}
+
+fn parse(buf: &[u8]) -> Result<Parsed, &'static str> {
+    if starts_with(buf, V2_SIGNATURE) {
+        if buf.len() < V2_SIGNATURE.len() {
+            Ok(Parsed::Incomplete)
+        } else {
+            parse_v2(buf)
+        }
+    } else if starts_with(buf, V1_PREFIX) {
+        if buf.len() < V1_PREFIX.len() {
+            Ok(Parsed::Incomplete)
+        } else {
+            parse_v1(buf)
+        }
+    } else {
+        Ok(Parsed::Missing)
+    }
+}
+
+/// Whether the buffer could be the start of something beginning with the
+/// prefix, as far as it goes.
+fn starts_with(buf: &[u8], prefix: &[u8]) -> bool {
+    let length = buf.len().min(prefix.len());
+    buf[..length] == prefix[..length]
+}
+
+/// Parse a text header, such as
+/// `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`.
+fn parse_v1(buf: &[u8]) -> Result<Parsed, &'static str> {
+    let end = match buf.windows(2).position(|window| window == b"\r\n") {
+        Some(end) => end,
+        None if buf.len() >= V1_MAX_LENGTH => return Err("the line is too long"),
+        None => return Ok(Parsed::Incomplete),
+    };
+    let line =
+        std::str::from_utf8(&buf[V1_PREFIX.len()..end]).map_err(|_| "the line isn't text")?;
+    let fields: Vec<&str> = line.split(' ').collect();
+
+    let (source, destination) = match fields.as_slice() {
+        ["UNKNOWN", ..] => (None, None),
+        [family @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] => {
+            let address = |ip: &str, port: &str| -> Result<SocketAddr, &'static str> {
+                let ip: IpAddr = ip.parse().map_err(|_| "an address is invalid")?;
+                if ip.is_ipv4() != (*family == "TCP4") {
+                    return Err("an address is from the wrong family");
+                }
+                let port = port.parse().map_err(|_| "a port is invalid")?;
+                Ok(SocketAddr::new(ip, port))
+            };
+            (
+                Some(address(source, source_port)?),
+                Some(address(destination, destination_port)?),
+            )
+        }
+        _ => return Err("the line isn't as expected"),
+    };
+
+    Ok(Parsed::Header(
+        ProxyHeader {
+            version: 1,
+            source,
+            destination,
+            tlvs: Vec::new(),
+        },
+        end + 2,
+    ))
+}
+
+/// Parse a binary header: the signature, then a byte for the version and
+/// command, one for the address family and protocol, and the length of the
+/// addresses and TLVs that follow.
+fn parse_v2(buf: &[u8]) -> Result<Parsed, &'static str> {
+    if buf.len() < V2_HEADER_LENGTH {
+        return Ok(Parsed::Incomplete);
+    }
+    let version_command = buf[12];
+    let family = buf[13];
+    let length = usize::from(u16::from_be_bytes([buf[14], buf[15]]));
+    if version_command >> 4 != 2 {
+        return Err("the version isn't 2");
+    }
+    if buf.len() < V2_HEADER_LENGTH + length {
+        return Ok(Parsed::Incomplete);
+    }
+    let body = &buf[V2_HEADER_LENGTH..V2_HEADER_LENGTH + length];
+
+    let address_length = match family >> 4 {
+        0x0 => 0,
+        0x1 => 12,
+        0x2 => 36,
+        0x3 => 216,
+        _ => return Err("the address family is unknown"),
+    };
+    if body.len() < address_length {
+        return Err("the addresses are cut short");
+    }
+    let (addresses, mut tlvs) = body.split_at(address_length);
+
+    let (source, destination) = match (version_command & 0x0f, family >> 4) {
+        // A LOCAL connection, made by the proxy itself for a health check
+        (0x0, _) => (None, None),
+        (0x1, 0x1) => (
+            Some(SocketAddr::new(
+                Ipv4Addr::from(u32::from_be_bytes(addresses[0..4].try_into().unwrap())).into(),
+                u16::from_be_bytes([addresses[8], addresses[9]]),
+            )),
+            Some(SocketAddr::new(
+                Ipv4Addr::from(u32::from_be_bytes(addresses[4..8].try_into().unwrap())).into(),
+                u16::from_be_bytes([addresses[10], addresses[11]]),
+            )),
+        ),
+        (0x1, 0x2) => (
+            Some(SocketAddr::new(
+                Ipv6Addr::from(u128::from_be_bytes(addresses[0..16].try_into().unwrap())).into(),
+                u16::from_be_bytes([addresses[32], addresses[33]]),
+            )),
+            Some(SocketAddr::new(
+                Ipv6Addr::from(u128::from_be_bytes(addresses[16..32].try_into().unwrap())).into(),
+                u16::from_be_bytes([addresses[34], addresses[35]]),
+            )),
+        ),
+        // Unix sockets and unspecified families have no address to use
+        (0x1, _) => (None, None),
+        _ => return Err("the command is unknown"),
+    };
+
+    let mut parsed_tlvs = Vec::new();
+    while !tlvs.is_empty() {
+        if tlvs.len() < 3 {
+            return Err("a TLV is cut short");
+        }
+        let kind = tlvs[0];
+        let value_length = usize::from(u16::from_be_bytes([tlvs[1], tlvs[2]]));
+        if tlvs.len() < 3 + value_length {
+            return Err("a TLV is cut short");
+        }
+        parsed_tlvs.push((kind, Bytes::copy_from_slice(&tlvs[3..3 + value_length])));
+        tlvs = &tlvs[3 + value_length..];
+    }
+
+    Ok(Parsed::Header(
+        ProxyHeader {
+            version: 2,
+            source,
+            destination,
+            tlvs: parsed_tlvs,
+        },
+        V2_HEADER_LENGTH + length,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_TCP4: &[u8] = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n";
+
+    fn v2(version_command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(version_command);
+        header.push(family);
+        header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        header.extend_from_slice(body);
+        header
+    }
+
+    fn v2_tcp4(tlvs: &[u8]) -> Vec<u8> {
+        let mut body = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        body.extend_from_slice(&56324u16.to_be_bytes());
+        body.extend_from_slice(&443u16.to_be_bytes());
+        body.extend_from_slice(tlvs);
+        v2(0x21, 0x11, &body)
+    }
+
+    fn tlv(kind: u8, value: &[u8]) -> Vec<u8> {
+        let mut tlv = vec![kind];
+        tlv.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        tlv.extend_from_slice(value);
+        tlv
+    }
+
+    fn expect_header(parsed: Result<Parsed, &'static str>) -> (ProxyHeader, usize) {
+        match parsed {
+            Ok(Parsed::Header(header, length)) => (header, length),
+            other => panic!("expected a header, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_v1_tcp4_up_to_the_line_ending() {
+        let mut buf = V1_TCP4.to_vec();
+        buf.extend_from_slice(b"GET / HTTP/1.1\r\n");
+        let (header, length) = expect_header(parse(&buf));
+        assert_eq!(length, V1_TCP4.len());
+        assert_eq!(header.version, 1);
+        assert_eq!(header.source, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(
+            header.destination,
+            Some("198.51.100.1:443".parse().unwrap())
+        );
+        assert!(header.tlvs.is_empty());
+    }
+
+    #[test]
+    fn parses_v1_tcp6() {
+        let (header, _) = expect_header(parse(b"PROXY TCP6 2001:db8::1 2001:db8::2 1234 8080\r\n"));
+        assert_eq!(header.source, Some("[2001:db8::1]:1234".parse().unwrap()));
+        assert_eq!(
+            header.destination,
+            Some("[2001:db8::2]:8080".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn v1_unknown_has_no_addresses() {
+        let (header, length) = expect_header(parse(b"PROXY UNKNOWN ffff::1 ffff::2 1 2\r\nGET"));
+        assert_eq!(length, 35);
+        assert_eq!(header.source, None);
+        assert_eq!(header.destination, None);
+    }
+
+    #[test]
+    fn truncated_v1_is_incomplete() {
+        for length in 0..V1_TCP4.len() {
+            assert_eq!(
+                parse(&V1_TCP4[..length]),
+                Ok(Parsed::Incomplete),
+                "at {}",
+                length
+            );
+        }
+    }
+
+    #[test]
+    fn v1_without_a_line_ending_is_too_long() {
+        let mut buf = b"PROXY TCP4 ".to_vec();
+        buf.resize(V1_MAX_LENGTH, b'1');
+        assert_eq!(parse(&buf), Err("the line is too long"));
+    }
+
+    #[test]
+    fn v1_rejects_addresses_from_the_wrong_family() {
+        assert_eq!(
+            parse(b"PROXY TCP4 2001:db8::1 198.51.100.1 1 2\r\n"),
+            Err("an address is from the wrong family")
+        );
+        assert_eq!(
+            parse(b"PROXY TCP6 192.0.2.1 2001:db8::2 1 2\r\n"),
+            Err("an address is from the wrong family")
+        );
+    }
+
+    #[test]
+    fn v1_rejects_malformed_lines() {
+        assert_eq!(
+            parse(b"PROXY TCP4 192.0.2.1 198.51.100.1 65536 443\r\n"),
+            Err("a port is invalid")
+        );
+        assert_eq!(
+            parse(b"PROXY TCP4 192.0.2.1 198.51.100.1 443\r\n"),
+            Err("the line isn't as expected")
+        );
+        assert_eq!(
+            parse(b"PROXY UDP4 192.0.2.1 198.51.100.1 1 2\r\n"),
+            Err("the line isn't as expected")
+        );
+    }
+
+    #[test]
+    fn other_protocols_are_missing_a_header() {
+        assert_eq!(parse(b"GET / HTTP/1.1\r\n"), Ok(Parsed::Missing));
+        assert_eq!(parse(b"\x16\x03\x01"), Ok(Parsed::Missing));
+        assert_eq!(parse(b"PROXY-"), Ok(Parsed::Missing));
+    }
+
+    #[test]
+    fn parses_v2_tcp4_with_tlvs() {
+        let mut tlvs = tlv(PP2_TYPE_SSL, &[PP2_CLIENT_SSL, 0, 0, 0, 0]);
+        tlvs.extend(tlv(0xea, b"\x01vpce-123"));
+        tlvs.extend(tlv(0x04, b""));
+        let mut buf = v2_tcp4(&tlvs);
+        let header_length = buf.len();
+        buf.extend_from_slice(b"GET");
+
+        let (header, length) = expect_header(parse(&buf));
+        assert_eq!(length, header_length);
+        assert_eq!(header.version, 2);
+        assert_eq!(header.source, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(
+            header.destination,
+            Some("198.51.100.1:443".parse().unwrap())
+        );
+        assert_eq!(
+            header.tlvs,
+            vec![
+                (
+                    PP2_TYPE_SSL,
+                    Bytes::from_static(&[PP2_CLIENT_SSL, 0, 0, 0, 0])
+                ),
+                (0xea, Bytes::from_static(b"\x01vpce-123")),
+                (0x04, Bytes::new()),
+            ]
+        );
+        assert!(header.is_secure());
+    }
+
+    #[test]
+    fn parses_v2_tcp6() {
+        let mut body = "2001:db8::9".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        body.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        body.extend_from_slice(&[0, 5, 0, 6]);
+        let (header, _) = expect_header(parse(&v2(0x21, 0x21, &body)));
+        assert_eq!(header.source, Some("[2001:db8::9]:5".parse().unwrap()));
+        assert_eq!(header.destination, Some("[::1]:6".parse().unwrap()));
+        assert!(!header.is_secure());
+    }
+
+    #[test]
+    fn truncated_v2_is_incomplete() {
+        let buf = v2_tcp4(&tlv(0xea, b"vpce"));
+        for length in 0..buf.len() {
+            assert_eq!(
+                parse(&buf[..length]),
+                Ok(Parsed::Incomplete),
+                "at {}",
+                length
+            );
+        }
+    }
+
+    #[test]
+    fn v2_local_command_has_no_addresses() {
+        // Health checks from the proxy itself may still carry its addresses
+        let mut buf = v2_tcp4(&[]);
+        buf[12] = 0x20;
+        let (header, length) = expect_header(parse(&buf));
+        assert_eq!(length, buf.len());
+        assert_eq!(header.source, None);
+        assert_eq!(header.destination, None);
+
+        let (header, length) = expect_header(parse(&v2(0x20, 0x00, &[])));
+        assert_eq!(length, V2_HEADER_LENGTH);
+        assert_eq!(header.source, None);
+    }
+
+    #[test]
+    fn v2_unix_and_unspecified_families_have_no_addresses() {
+        let (header, _) = expect_header(parse(&v2(0x21, 0x31, &[0; 216])));
+        assert_eq!(header.source, None);
+        let (header, _) = expect_header(parse(&v2(0x21, 0x00, &[])));
+        assert_eq!(header.source, None);
+    }
+
+    #[test]
+    fn v2_rejects_the_wrong_version_family_or_command() {
+        let mut buf = v2_tcp4(&[]);
+        buf[12] = 0x11;
+        assert_eq!(parse(&buf), Err("the version isn't 2"));
+
+        let mut buf = v2_tcp4(&[]);
+        buf[13] = 0x41;
+        assert_eq!(parse(&buf), Err("the address family is unknown"));
+
+        let mut buf = v2_tcp4(&[]);
+        buf[12] = 0x22;
+        assert_eq!(parse(&buf), Err("the command is unknown"));
+    }
+
+    #[test]
+    fn v2_rejects_addresses_shorter_than_their_family() {
+        // An IPv6 family with only enough room for IPv4 addresses
+        assert_eq!(
+            parse(&v2(0x21, 0x21, &[0; 12])),
+            Err("the addresses are cut short")
+        );
+    }
+
+    #[test]
+    fn v2_rejects_bad_tlv_lengths() {
+        // A TLV that says it is longer than what is left of the header
+        let mut tlvs = vec![0x05];
+        tlvs.extend_from_slice(&9u16.to_be_bytes());
+        tlvs.extend_from_slice(b"ab");
+        assert_eq!(parse(&v2_tcp4(&tlvs)), Err("a TLV is cut short"));
+
+        // Trailing bytes too short to be a TLV
+        assert_eq!(parse(&v2_tcp4(&[0x05, 0x00])), Err("a TLV is cut short"));
+    }
+
+    #[cfg(unix)]
+    mod accept {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::UnixStream,
+        };
+
+        use super::*;
+
+        async fn accept_after(sent: &[u8]) -> io::Result<(Stream, ProxyHeader)> {
+            let (mut client, server) = UnixStream::pair().unwrap();
+            client.write_all(sent).await.unwrap();
+            client.shutdown().await.unwrap();
+            accept(Stream::Unix(server)).await
+        }
+
+        #[tokio::test]
+        async fn reads_the_rest_of_the_connection_after_the_header() {
+            let mut sent = V1_TCP4.to_vec();
+            sent.extend_from_slice(b"GET / HTTP/1.1\r\n\r\n");
+            let (mut stream, header) = accept_after(&sent).await.unwrap();
+            assert_eq!(header.source, Some("192.0.2.1:56324".parse().unwrap()));
+
+            let mut rest = Vec::new();
+            stream.read_to_end(&mut rest).await.unwrap();
+            assert_eq!(rest, b"GET / HTTP/1.1\r\n\r\n");
+        }
+
+        #[tokio::test]
+        async fn rejects_connections_without_a_header() {
+            let io_err = accept_after(b"GET / HTTP/1.1\r\n\r\n").await.err().unwrap();
+            assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[tokio::test]
+        async fn rejects_connections_that_close_within_the_header() {
+            let io_err = accept_after(&V1_TCP4[..20]).await.err().unwrap();
+            assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
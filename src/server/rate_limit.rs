@@ -0,0 +1,318 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    net::{IpAddr, Ipv6Addr},
+    sync::Mutex,
+    time::Duration,
+};
+
+use percent_encoding::percent_decode_str;
+use tokio::time::Instant;
+
+/// How often buckets that have filled back up are forgotten.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A token bucket: each client can make `burst` requests at once, and gets
+/// back `per_second` requests a second after that.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+/// Rate limits on the requests from each client IP address.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimitConfig {
+    /// The limit on requests that no path limit applies to.
+    pub global: Option<RateLimit>,
+    /// Limits on requests whose path is under a prefix, which replace the
+    /// global limit for them. Prefixes match whole path segments, so that
+    /// `/api` applies to `/api/users` but not `/apiary`, and the longest
+    /// matching prefix applies.
+    pub paths: BTreeMap<String, RateLimit>,
+}
+
+impl RateLimitConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.global.is_some() || !self.paths.is_empty()
+    }
+}
+
+/// Keeps a token bucket for each client and limit, which is shared by all
+/// of a server's connections.
+pub struct RateLimiter {
+    /// The path limits, normalized and longest prefix first, then the
+    /// global limit under an empty prefix.
+    limits: Vec<(String, RateLimit)>,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    /// Keyed by the index of the limit and the client's address, or its
+    /// /64 network for IPv6 clients.
+    buckets: HashMap<(usize, IpAddr), Bucket>,
+    last_sweep: Instant,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let earned = now.duration_since(self.updated).as_secs_f64() * limit.per_second;
+        self.tokens = (self.tokens + earned).min(f64::from(limit.burst));
+        self.updated = now;
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let mut limits: Vec<_> = config
+            .paths
+            .iter()
+            .map(|(prefix, limit)| (normalize_path(prefix), *limit))
+            .collect();
+        limits.sort_by_key(|(prefix, _)| Reverse(prefix.len()));
+        limits.extend(config.global.map(|limit| (String::new(), limit)));
+
+        RateLimiter {
+            limits,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a request from the client's bucket for the path. If it is
+    /// empty, the number of seconds until it won't be is returned instead.
+    pub fn check(&self, ip: IpAddr, path: &str) -> Result<(), u64> {
+        self.check_at(ip, path, Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, path: &str, now: Instant) -> Result<(), u64> {
+        // Limits apply to the path that the app sees, however it was
+        // encoded, so that `/%6Cogin` or `/./login` can't get around a
+        // limit on `/login`
+        let path = normalize_path(path);
+        let index = match self.limits.iter().position(|(prefix, _)| {
+            path.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        }) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let limit = &self.limits[index].1;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.duration_since(buckets.last_sweep) >= SWEEP_INTERVAL {
+            self.sweep(&mut buckets, now);
+        }
+        let bucket = buckets
+            .buckets
+            .entry((index, client_key(ip)))
+            .or_insert(Bucket {
+                tokens: f64::from(limit.burst),
+                updated: now,
+            });
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / limit.per_second;
+            Err(wait.ceil().max(1.0) as u64)
+        }
+    }
+
+    /// Forget clients whose buckets have filled back up, which are no
+    /// different from new ones.
+    fn sweep(&self, buckets: &mut Buckets, now: Instant) {
+        buckets.buckets.retain(|(index, _), bucket| {
+            let limit = &self.limits[*index].1;
+            bucket.refill(limit, now);
+            bucket.tokens < f64::from(limit.burst)
+        });
+        buckets.last_sweep = now;
+    }
+}
+
+/// What a client's requests are counted under: its address, or the /64
+/// network for IPv6, which is the smallest that one client is usually
+/// given.
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX))),
+        ip => ip,
+    }
+}
+
+/// A path with percent-encoding decoded, empty and `.` segments removed,
+/// and `..` segments applied. There is no trailing slash, so the root path
+/// is empty.
+fn normalize_path(path: &str) -> String {
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments
+        .iter()
+        .map(|segment| format!("/{}", segment))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+
+    fn limiter(global: Option<(f64, u32)>, paths: &[(&str, f64, u32)]) -> RateLimiter {
+        let limit = |(per_second, burst)| RateLimit { per_second, burst };
+        RateLimiter::new(&RateLimitConfig {
+            global: global.map(limit),
+            paths: paths
+                .iter()
+                .map(|&(prefix, per_second, burst)| {
+                    (prefix.to_string(), limit((per_second, burst)))
+                })
+                .collect(),
+        })
+    }
+
+    /// How many requests in a row are allowed at `now`.
+    fn allowed(limiter: &RateLimiter, ip: IpAddr, path: &str, now: Instant) -> usize {
+        (0..100)
+            .take_while(|_| limiter.check_at(ip, path, now).is_ok())
+            .count()
+    }
+
+    #[test]
+    fn allows_a_burst_then_rejects() {
+        let limiter = limiter(Some((1.0, 3)), &[]);
+        let now = Instant::now();
+
+        assert_eq!(allowed(&limiter, CLIENT, "/", now), 3);
+        assert_eq!(limiter.check_at(CLIENT, "/", now), Err(1));
+    }
+
+    #[test]
+    fn refills_at_the_rate_up_to_the_burst() {
+        let limiter = limiter(Some((2.0, 4)), &[]);
+        let now = Instant::now();
+        assert_eq!(allowed(&limiter, CLIENT, "/", now), 4);
+
+        let now = now + Duration::from_millis(1500);
+        assert_eq!(allowed(&limiter, CLIENT, "/", now), 3);
+
+        let now = now + Duration::from_secs(60);
+        assert_eq!(allowed(&limiter, CLIENT, "/", now), 4);
+    }
+
+    #[test]
+    fn retry_after_rounds_up_to_whole_seconds() {
+        let limiter = limiter(Some((0.4, 1)), &[]);
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(CLIENT, "/", now), Ok(()));
+
+        // 2.5 seconds until the next token
+        assert_eq!(limiter.check_at(CLIENT, "/", now), Err(3));
+        // 0.25 seconds, which is still at least one
+        let now = now + Duration::from_millis(1875);
+        assert_eq!(limiter.check_at(CLIENT, "/", now), Err(1));
+    }
+
+    #[test]
+    fn longest_matching_prefix_applies() {
+        let limiter = limiter(Some((1.0, 5)), &[("/api", 1.0, 2), ("/api/login", 1.0, 1)]);
+        let now = Instant::now();
+
+        assert_eq!(allowed(&limiter, CLIENT, "/api/login", now), 1);
+        assert_eq!(allowed(&limiter, CLIENT, "/api/users", now), 2);
+        assert_eq!(allowed(&limiter, CLIENT, "/", now), 5);
+    }
+
+    #[test]
+    fn prefixes_match_whole_segments() {
+        let limiter = limiter(None, &[("/api/", 1.0, 1)]);
+        let now = Instant::now();
+
+        assert_eq!(allowed(&limiter, CLIENT, "/api", now), 1);
+        assert_eq!(limiter.check_at(CLIENT, "/api/users", now), Err(1));
+        assert_eq!(allowed(&limiter, CLIENT, "/apiary", now), 100);
+    }
+
+    #[test]
+    fn paths_are_decoded_and_normalized() {
+        let limiter = limiter(None, &[("/login", 1.0, 1)]);
+        let now = Instant::now();
+        assert_eq!(limiter.check_at(CLIENT, "/login", now), Ok(()));
+
+        for path in &[
+            "/%6Cogin",
+            "//login",
+            "/./login",
+            "/static/../login",
+            "/login/",
+        ] {
+            assert_eq!(limiter.check_at(CLIENT, path, now), Err(1), "{}", path);
+        }
+    }
+
+    #[test]
+    fn unlimited_paths_are_allowed() {
+        let limiter = limiter(None, &[("/login", 1.0, 1)]);
+
+        assert_eq!(allowed(&limiter, CLIENT, "/", Instant::now()), 100);
+    }
+
+    #[test]
+    fn clients_have_their_own_buckets() {
+        let limiter = limiter(Some((1.0, 1)), &[]);
+        let now = Instant::now();
+
+        assert_eq!(allowed(&limiter, CLIENT, "/", now), 1);
+        assert_eq!(allowed(&limiter, "192.0.2.2".parse().unwrap(), "/", now), 1);
+    }
+
+    #[test]
+    fn ipv6_clients_share_a_bucket_per_64() {
+        let limiter = limiter(Some((1.0, 1)), &[]);
+        let now = Instant::now();
+
+        assert_eq!(
+            allowed(&limiter, "2001:db8::1".parse().unwrap(), "/", now),
+            1
+        );
+        assert_eq!(
+            allowed(&limiter, "2001:db8::ffff:1".parse().unwrap(), "/", now),
+            0
+        );
+        assert_eq!(
+            allowed(&limiter, "2001:db8:0:1::1".parse().unwrap(), "/", now),
+            1
+        );
+    }
+
+    #[test]
+    fn ipv4_mapped_clients_share_the_ipv4_bucket() {
+        let limiter = limiter(Some((1.0, 1)), &[]);
+        let now = Instant::now();
+
+        assert_eq!(allowed(&limiter, CLIENT, "/", now), 1);
+        assert_eq!(
+            allowed(&limiter, "::ffff:192.0.2.1".parse().unwrap(), "/", now),
+            0
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::time::{Interval, MissedTickBehavior};
+
+/// How often the watched directories are checked for changes.
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long files must go unchanged before reloading, so that saving
+/// several files at once only reloads once.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Files which are always watched.
+const DEFAULT_INCLUDES: &[&str] = &["*.py"];
+
+/// Files and directories which are never watched.
+const DEFAULT_EXCLUDES: &[&str] = &[".*", "*.py[cod]", "*~", "__pycache__"];
+
+/// Which files are watched for changes when reloading is enabled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReloadConfig {
+    /// The directories to watch, or the working directory if empty.
+    pub dirs: Vec<PathBuf>,
+    /// Glob patterns of files to watch, in addition to `*.py`.
+    pub includes: Vec<String>,
+    /// Glob patterns of files and directories to ignore, in addition to
+    /// hidden files, `__pycache__` and compiled Python files.
+    pub excludes: Vec<String>,
+}
+
+impl ReloadConfig {
+    fn dirs(&self) -> Vec<PathBuf> {
+        if self.dirs.is_empty() {
+            vec![PathBuf::from(".")]
+        } else {
+            self.dirs.clone()
+        }
+    }
+}
+
+/// Watches source files by polling their modification times, which works
+/// the same on every platform and filesystem.
+pub struct Reloader {
+    watched: Arc<WatchedFiles>,
+    checks: Interval,
+    /// The files as of the last check, once there has been one.
+    file_stamps: Option<HashMap<PathBuf, (SystemTime, u64)>>,
+    /// A change that is waiting for the files to settle, which is kept
+    /// here in case `next_change` is cancelled meanwhile.
+    pending_change: Option<PathBuf>,
+}
+
+/// The directories and patterns that make up the watched files.
+struct WatchedFiles {
+    dirs: Vec<PathBuf>,
+    includes: Vec<String>,
+    excludes: Vec<String>,
+}
+
+impl Reloader {
+    pub fn new(config: &ReloadConfig) -> Self {
+        let watched = WatchedFiles {
+            dirs: config.dirs(),
+            includes: DEFAULT_INCLUDES
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .chain(config.includes.iter().cloned())
+                .collect(),
+            excludes: DEFAULT_EXCLUDES
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .chain(config.excludes.iter().cloned())
+                .collect(),
+        };
+        for dir in &watched.dirs {
+            log::info!("Watching for changes in {}", dir.display());
+        }
+        let mut checks = tokio::time::interval(CHECK_INTERVAL);
+        checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Reloader {
+            watched: Arc::new(watched),
+            checks,
+            file_stamps: None,
+            pending_change: None,
+        }
+    }
+
+    /// Resolves with a file that was added, changed or removed, once the
+    /// watched files have settled.
+    pub async fn next_change(&mut self) -> PathBuf {
+        if self.file_stamps.is_none() {
+            self.file_stamps = Some(self.watched.clone().scan().await);
+        }
+
+        while self.pending_change.is_none() {
+            self.checks.tick().await;
+            self.pending_change = self.check().await;
+        }
+
+        loop {
+            tokio::time::sleep(DEBOUNCE).await;
+            if self.check().await.is_none() {
+                return self.pending_change.take().unwrap();
+            }
+        }
+    }
+
+    /// Compare the watched files with when they were last checked, giving
+    /// one that changed.
+    async fn check(&mut self) -> Option<PathBuf> {
+        let file_stamps = self.watched.clone().scan().await;
+        let last_stamps = self.file_stamps.get_or_insert_with(HashMap::new);
+        let changed = file_stamps
+            .iter()
+            .find(|(path, stamp)| last_stamps.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .or_else(|| {
+                last_stamps
+                    .keys()
+                    .find(|path| !file_stamps.contains_key(*path))
+                    .cloned()
+            });
+        self.file_stamps = Some(file_stamps);
+        changed
+    }
+}
+
+impl WatchedFiles {
+    /// The modification time and size of every watched file. Walking large
+    /// trees takes a while, so it's done on a blocking thread.
+    async fn scan(self: Arc<Self>) -> HashMap<PathBuf, (SystemTime, u64)> {
+        tokio::task::spawn_blocking(move || {
+            let mut file_stamps = HashMap::new();
+            for dir in &self.dirs {
+                self.scan_dir(dir, dir, &mut file_stamps);
+            }
+            file_stamps
+        })
+        .await
+        .unwrap_or_default()
+    }
+
+    fn scan_dir(
+        &self,
+        root: &Path,
+        dir: &Path,
+        file_stamps: &mut HashMap<PathBuf, (SystemTime, u64)>,
+    ) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(io_err) => {
+                log::debug!("Could not watch {}: {}", dir.display(), io_err);
+                return;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let relative_path = path.strip_prefix(root).unwrap_or(&path);
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+
+            // Symlinked directories aren't followed, so that a link cycle
+            // can't make the scan endless.
+            if file_type.is_dir() {
+                if !self.is_excluded(relative_path) {
+                    self.scan_dir(root, &path, file_stamps);
+                }
+            } else if self.is_included(relative_path) {
+                if let Ok(metadata) = fs::metadata(&path) {
+                    if let Ok(modified) = metadata.modified() {
+                        file_stamps.insert(path, (modified, metadata.len()));
+                    }
+                }
+            }
+        }
+    }
+
+    fn is_included(&self, path: &Path) -> bool {
+        self.includes
+            .iter()
+            .any(|pattern| path_matches(pattern, path))
+            && !self.is_excluded(path)
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.excludes
+            .iter()
+            .any(|pattern| path_matches(pattern, path))
+    }
+}
+
+/// Whether a path matches a glob pattern. Patterns without a `/` match the
+/// file name, and others match the whole path, in which a `**` segment
+/// matches any number of directories.
+fn path_matches(pattern: &str, path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    if pattern.contains('/') {
+        glob_matches(pattern, &path)
+    } else {
+        let name = path.rsplit('/').next().unwrap_or(&path);
+        segment_matches(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+/// Match a path against a glob pattern one segment at a time, where a `**`
+/// segment matches any number of segments.
+///
+/// Only the last `**` is ever backtracked to: anything an earlier one could
+/// match, the later one can match instead. So each segment of the path is
+/// retried at most once per segment of the pattern.
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<_> = pattern.split('/').collect();
+    let path: Vec<_> = path.split('/').collect();
+    let (mut pattern_index, mut path_index) = (0, 0);
+    let mut backtrack = None;
+
+    while path_index < path.len() {
+        match pattern.get(pattern_index) {
+            Some(&"**") => {
+                backtrack = Some((pattern_index + 1, path_index));
+                pattern_index += 1;
+                continue;
+            }
+            Some(segment) if segment_matches(segment.as_bytes(), path[path_index].as_bytes()) => {
+                pattern_index += 1;
+                path_index += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match &mut backtrack {
+            Some((after_stars, skipped_to)) => {
+                *skipped_to += 1;
+                pattern_index = *after_stars;
+                path_index = *skipped_to;
+            }
+            None => return false,
+        }
+    }
+    pattern[pattern_index..]
+        .iter()
+        .all(|segment| *segment == "**")
+}
+
+/// Match a path segment against a glob pattern with `*`, `?` and `[...]`,
+/// backtracking to the last `*` in the same way as `glob_matches`.
+fn segment_matches(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut pattern_index, mut text_index) = (0, 0);
+    let mut backtrack = None;
+
+    while text_index < text.len() {
+        match pattern.get(pattern_index) {
+            Some(b'*') => {
+                backtrack = Some((pattern_index + 1, text_index));
+                pattern_index += 1;
+                continue;
+            }
+            Some(_) => {
+                if let Some(len) = byte_matches(&pattern[pattern_index..], text[text_index]) {
+                    pattern_index += len;
+                    text_index += 1;
+                    continue;
+                }
+            }
+            None => {}
+        }
+        match &mut backtrack {
+            Some((after_star, skipped_to)) => {
+                *skipped_to += 1;
+                pattern_index = *after_star;
+                text_index = *skipped_to;
+            }
+            None => return false,
+        }
+    }
+    pattern[pattern_index..].iter().all(|byte| *byte == b'*')
+}
+
+/// Whether the start of a pattern, other than a `*`, matches a byte, giving
+/// the length of the part that matched.
+fn byte_matches(pattern: &[u8], byte: u8) -> Option<usize> {
+    match pattern {
+        [b'?', ..] => Some(1),
+        [b'[', class @ ..] => match class.iter().position(|byte| *byte == b']') {
+            Some(end) => {
+                let (negated, class) = match &class[..end] {
+                    [b'!', class @ ..] | [b'^', class @ ..] => (true, class),
+                    class => (false, class),
+                };
+                (class_matches(class, byte) != negated).then_some(end + 2)
+            }
+            None => (byte == b'[').then_some(1),
+        },
+        [expected, ..] => (*expected == byte).then_some(1),
+        [] => None,
+    }
+}
+
+fn class_matches(class: &[u8], byte: u8) -> bool {
+    let mut index = 0;
+    while index < class.len() {
+        if index + 2 < class.len() && class[index + 1] == b'-' {
+            if (class[index]..=class[index + 2]).contains(&byte) {
+                return true;
+            }
+            index += 3;
+        } else {
+            if class[index] == byte {
+                return true;
+            }
+            index += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        path_matches(pattern, Path::new(path))
+    }
+
+    #[test]
+    fn patterns_without_a_slash_match_the_file_name() {
+        assert!(matches("*.py", "app.py"));
+        assert!(matches("*.py", "pkg/sub/app.py"));
+        assert!(!matches("*.py", "app.pyc"));
+        assert!(!matches("*.py", "app.py/readme"));
+        assert!(matches("__pycache__", "pkg/__pycache__"));
+        assert!(matches(".*", "pkg/.git"));
+    }
+
+    #[test]
+    fn star_does_not_cross_directories() {
+        assert!(matches("src/*.py", "src/app.py"));
+        assert!(!matches("src/*.py", "src/pkg/app.py"));
+        assert!(matches("src/*/app.py", "src/pkg/app.py"));
+        assert!(matches("*", ""));
+        assert!(matches("a*b*c", "abc"));
+        assert!(matches("a*b*c", "axxbyybzc"));
+        assert!(!matches("a*b*c", "axxbyyb"));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        assert!(matches("**/templates/*.html", "templates/index.html"));
+        assert!(matches("**/templates/*.html", "a/b/templates/index.html"));
+        assert!(matches("src/**/*.py", "src/app.py"));
+        assert!(matches("src/**/*.py", "src/a/b/app.py"));
+        assert!(!matches("src/**/*.py", "lib/a/app.py"));
+        assert!(matches("src/**", "src/a/b"));
+        assert!(matches("a/**/b/**/c", "a/x/b/y/b/z/c"));
+        assert!(!matches("a/**/b/**/c", "a/x/c/y/b"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches("app.py?", "app.pyc"));
+        assert!(!matches("app.py?", "app.py"));
+        assert!(!matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn classes_match_one_of_their_characters() {
+        assert!(matches("*.py[cod]", "app.pyc"));
+        assert!(matches("*.py[cod]", "app.pyo"));
+        assert!(!matches("*.py[cod]", "app.pyx"));
+        assert!(matches("v[0-9].txt", "v7.txt"));
+        assert!(!matches("v[!0-9].txt", "v7.txt"));
+        assert!(matches("v[^0-9].txt", "vx.txt"));
+        assert!(matches("a[b", "a[b"));
+    }
+
+    #[test]
+    fn many_stars_do_not_backtrack_exponentially() {
+        let name = "a".repeat(10_000);
+        assert!(!matches("*a*a*a*a*a*a*a*a*a*b", &name));
+
+        let path = vec!["a"; 2_000].join("/");
+        assert!(!matches("**/a/**/a/**/a/**/a/**/b", &path));
+    }
+}
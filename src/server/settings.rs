@@ -1,15 +1,59 @@
+use std::collections::BTreeMap;
 use std::fmt;
-use std::net::{IpAddr, SocketAddr};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::Duration;
 
-use hyper::server::conn::AddrIncoming;
-use hyper::server::Builder;
-use hyper::Server;
 use pyo3::{class::basic::PyObjectProtocol, exceptions::PyValueError, prelude::*};
 
+use super::limits::LimitConfig;
+use super::listener::{BindConfig, BindTarget, UnixSocketConfig};
+use super::proxy::{ProxyConfig, TrustedProxy};
+use super::rate_limit::{RateLimit, RateLimitConfig};
+#[cfg(unix)]
+use super::reloader::ReloadConfig;
+use super::shutdown::{shutdown_timeout, ShutdownConfig};
+#[cfg(unix)]
+use super::supervisor::SupervisorConfig;
+use super::timeouts::{MinDataRate, TimeoutConfig};
+use super::tls::{ClientAuth, TlsConfig, TlsVersion};
+use super::worker::WorkerConfig;
+use crate::http::HttpConfig;
+use crate::lifespan::LifespanMode;
+
+/// How long clients are waited on before minimum data rates apply, by
+/// default.
+const DEFAULT_MIN_RATE_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
 #[pyclass(module = "asgi_server")]
 pub struct Settings {
-    host: IpAddr,
+    host: String,
     port: u16,
+    lifespan: LifespanMode,
+    tls: TlsConfig,
+    bind: Vec<BindTarget>,
+    uds: Option<String>,
+    unix_socket: UnixSocketConfig,
+    fd: Option<i32>,
+    socket_activation: bool,
+    shutdown: ShutdownConfig,
+    workers: usize,
+    worker: WorkerConfig,
+    max_memory: Option<u64>,
+    reload: bool,
+    reload_dirs: Vec<String>,
+    reload_includes: Vec<String>,
+    reload_excludes: Vec<String>,
+    http: HttpConfig,
+    timeouts: TimeoutConfig,
+    min_request_body_rate: Option<u64>,
+    min_response_rate: Option<u64>,
+    min_rate_grace_period: Duration,
+    limits: LimitConfig,
+    rate_limit: Option<f64>,
+    rate_limit_burst: Option<u32>,
+    path_rate_limits: BTreeMap<String, (f64, u32)>,
+    proxy: ProxyConfig,
 }
 
 #[pymethods]
@@ -21,17 +65,17 @@ impl Settings {
 
     #[getter]
     fn get_host(&self) -> String {
-        self.host.to_string()
+        self.host.clone()
     }
 
+    /// A hostname or IP address, which may resolve to several addresses
+    /// that are all listened on.
     #[setter]
     fn set_host(&mut self, value: &str) -> PyResult<()> {
-        self.host = match value {
-            "localhost" => Ok(IpAddr::from([127, 0, 0, 1])),
-            _ => value.parse::<IpAddr>().map_err(|_| {
-                PyValueError::new_err(format!("'{}' is not a valid ip address", value))
-            }),
-        }?;
+        if value.is_empty() {
+            return Err(PyValueError::new_err("host must not be empty"));
+        }
+        self.host = value.to_string();
         Ok(())
     }
 
@@ -45,6 +89,631 @@ impl Settings {
         self.port = value;
         Ok(())
     }
+
+    #[getter]
+    fn get_lifespan(&self) -> String {
+        self.lifespan.to_string()
+    }
+
+    #[setter]
+    fn set_lifespan(&mut self, value: &str) -> PyResult<()> {
+        self.lifespan = value.parse::<LifespanMode>()?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_certfile(&self) -> Option<String> {
+        self.tls.certfile.clone()
+    }
+
+    #[setter]
+    fn set_certfile(&mut self, value: Option<String>) -> PyResult<()> {
+        self.tls.certfile = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_keyfile(&self) -> Option<String> {
+        self.tls.keyfile.clone()
+    }
+
+    #[setter]
+    fn set_keyfile(&mut self, value: Option<String>) -> PyResult<()> {
+        self.tls.keyfile = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_ca_certs(&self) -> Option<String> {
+        self.tls.ca_certs.clone()
+    }
+
+    #[setter]
+    fn set_ca_certs(&mut self, value: Option<String>) -> PyResult<()> {
+        self.tls.ca_certs = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_ciphers(&self) -> Option<String> {
+        self.tls.ciphers.clone()
+    }
+
+    #[setter]
+    fn set_ciphers(&mut self, value: Option<String>) -> PyResult<()> {
+        self.tls.ciphers = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_tls_min_version(&self) -> String {
+        self.tls.min_version.to_string()
+    }
+
+    #[setter]
+    fn set_tls_min_version(&mut self, value: &str) -> PyResult<()> {
+        self.tls.min_version = value.parse::<TlsVersion>()?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_client_ca_certs(&self) -> Option<String> {
+        self.tls.client_ca_certs.clone()
+    }
+
+    #[setter]
+    fn set_client_ca_certs(&mut self, value: Option<String>) -> PyResult<()> {
+        self.tls.client_ca_certs = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_client_auth(&self) -> String {
+        self.tls.client_auth.to_string()
+    }
+
+    #[setter]
+    fn set_client_auth(&mut self, value: &str) -> PyResult<()> {
+        self.tls.client_auth = value.parse::<ClientAuth>()?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_sni_certs(&self) -> BTreeMap<String, (String, String)> {
+        self.tls.sni_certs.clone()
+    }
+
+    #[setter]
+    fn set_sni_certs(&mut self, value: BTreeMap<String, (String, String)>) -> PyResult<()> {
+        self.tls.sni_certs = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_tls_reload_interval(&self) -> Option<f64> {
+        self.tls
+            .reload_interval
+            .map(|interval| interval.as_secs_f64())
+    }
+
+    #[setter]
+    fn set_tls_reload_interval(&mut self, value: Option<f64>) -> PyResult<()> {
+        self.tls.reload_interval = match value {
+            Some(seconds) if seconds > 0.0 && seconds.is_finite() => {
+                Some(Duration::from_secs_f64(seconds))
+            }
+            Some(_) => {
+                return Err(PyValueError::new_err(
+                    "tls_reload_interval must be a positive number of seconds",
+                ))
+            }
+            None => None,
+        };
+        Ok(())
+    }
+
+    #[getter]
+    fn get_bind(&self) -> Vec<String> {
+        self.bind.iter().map(ToString::to_string).collect()
+    }
+
+    /// Addresses to listen on, in the form `HOST:PORT`, `[IPV6]:PORT`,
+    /// `unix:PATH` or `fd://FD`. These replace `host` and `port`.
+    #[setter]
+    fn set_bind(&mut self, value: Vec<String>) -> PyResult<()> {
+        self.bind = value
+            .iter()
+            .map(|target| target.parse::<BindTarget>())
+            .collect::<PyResult<_>>()?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_uds(&self) -> Option<String> {
+        self.uds.clone()
+    }
+
+    #[setter]
+    fn set_uds(&mut self, value: Option<String>) -> PyResult<()> {
+        self.uds = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_uds_mode(&self) -> Option<u32> {
+        self.unix_socket.mode
+    }
+
+    #[setter]
+    fn set_uds_mode(&mut self, value: Option<u32>) -> PyResult<()> {
+        if value.is_some_and(|mode| mode > 0o7777) {
+            return Err(PyValueError::new_err(
+                "uds_mode must be a file mode, such as 0o660",
+            ));
+        }
+        self.unix_socket.mode = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_uds_owner(&self) -> Option<u32> {
+        self.unix_socket.owner
+    }
+
+    #[setter]
+    fn set_uds_owner(&mut self, value: Option<u32>) -> PyResult<()> {
+        self.unix_socket.owner = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_uds_group(&self) -> Option<u32> {
+        self.unix_socket.group
+    }
+
+    #[setter]
+    fn set_uds_group(&mut self, value: Option<u32>) -> PyResult<()> {
+        self.unix_socket.group = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_fd(&self) -> Option<i32> {
+        self.fd
+    }
+
+    /// Takes either a file descriptor, or a socket object with a `fileno()`
+    /// method. The socket must stay open until the server has started.
+    #[setter]
+    fn set_fd(&mut self, value: Option<&PyAny>) -> PyResult<()> {
+        self.fd = match value {
+            Some(socket) if socket.hasattr("fileno")? => {
+                Some(socket.call_method0("fileno")?.extract()?)
+            }
+            Some(fd) => Some(fd.extract()?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    #[getter]
+    fn get_socket_activation(&self) -> bool {
+        self.socket_activation
+    }
+
+    /// Listen on the sockets passed by systemd. `LISTEN_FDS` and
+    /// `LISTEN_PID` are left set, as changing the environment isn't safe
+    /// once the server is running; subprocesses can tell that the sockets
+    /// weren't meant for them, as `LISTEN_PID` isn't their pid.
+    #[setter]
+    fn set_socket_activation(&mut self, value: bool) -> PyResult<()> {
+        self.socket_activation = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_shutdown_timeout(&self) -> Option<f64> {
+        self.shutdown.timeout.map(|timeout| timeout.as_secs_f64())
+    }
+
+    /// How long a graceful shutdown waits for in-flight requests before
+    /// closing their connections. `None` waits for as long as they take.
+    #[setter]
+    fn set_shutdown_timeout(&mut self, value: Option<f64>) -> PyResult<()> {
+        self.shutdown.timeout = value.map(shutdown_timeout).transpose()?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_workers(&self) -> usize {
+        self.workers
+    }
+
+    /// How many worker processes `start_supervisor` runs. `start_server`
+    /// always serves from the calling process.
+    #[setter]
+    fn set_workers(&mut self, value: usize) -> PyResult<()> {
+        if value == 0 {
+            return Err(PyValueError::new_err("workers must be at least 1"));
+        }
+        self.workers = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_max_requests(&self) -> Option<u64> {
+        self.worker.max_requests
+    }
+
+    /// How many requests a worker serves before it is replaced.
+    #[setter]
+    fn set_max_requests(&mut self, value: Option<u64>) -> PyResult<()> {
+        if value == Some(0) {
+            return Err(PyValueError::new_err("max_requests must be at least 1"));
+        }
+        self.worker.max_requests = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_max_requests_jitter(&self) -> u64 {
+        self.worker.max_requests_jitter
+    }
+
+    #[setter]
+    fn set_max_requests_jitter(&mut self, value: u64) -> PyResult<()> {
+        self.worker.max_requests_jitter = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_supervisor_link_fd(&self) -> Option<i32> {
+        self.worker.supervisor_link_fd
+    }
+
+    /// The file descriptor that a worker process reaches its supervisor
+    /// through. This is set by the workers that `start_supervisor` runs.
+    #[setter]
+    fn set_supervisor_link_fd(&mut self, value: Option<i32>) -> PyResult<()> {
+        self.worker.supervisor_link_fd = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_max_memory(&self) -> Option<u64> {
+        self.max_memory
+    }
+
+    /// The resident memory, in MiB, that a worker may use before it is
+    /// replaced.
+    #[setter]
+    fn set_max_memory(&mut self, value: Option<u64>) -> PyResult<()> {
+        if value == Some(0) {
+            return Err(PyValueError::new_err("max_memory must be at least 1 MiB"));
+        }
+        self.max_memory = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_reload(&self) -> bool {
+        self.reload
+    }
+
+    /// Whether `start_supervisor` replaces its workers when source files
+    /// change.
+    #[setter]
+    fn set_reload(&mut self, value: bool) -> PyResult<()> {
+        self.reload = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_reload_dirs(&self) -> Vec<String> {
+        self.reload_dirs.clone()
+    }
+
+    /// The directories watched for changes, or the working directory if
+    /// empty.
+    #[setter]
+    fn set_reload_dirs(&mut self, value: Vec<String>) -> PyResult<()> {
+        self.reload_dirs = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_reload_includes(&self) -> Vec<String> {
+        self.reload_includes.clone()
+    }
+
+    /// Glob patterns of files to watch, besides `*.py`.
+    #[setter]
+    fn set_reload_includes(&mut self, value: Vec<String>) -> PyResult<()> {
+        self.reload_includes = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_reload_excludes(&self) -> Vec<String> {
+        self.reload_excludes.clone()
+    }
+
+    /// Glob patterns of files and directories not to watch.
+    #[setter]
+    fn set_reload_excludes(&mut self, value: Vec<String>) -> PyResult<()> {
+        self.reload_excludes = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_max_body_size(&self) -> Option<u64> {
+        self.http.max_body_size
+    }
+
+    /// The largest request body, in bytes, that the app is given.
+    #[setter]
+    fn set_max_body_size(&mut self, value: Option<u64>) -> PyResult<()> {
+        self.http.max_body_size = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_header_read_timeout(&self) -> Option<f64> {
+        self.timeouts
+            .header_read
+            .map(|timeout| timeout.as_secs_f64())
+    }
+
+    /// How long a client has to send a request's headers.
+    #[setter]
+    fn set_header_read_timeout(&mut self, value: Option<f64>) -> PyResult<()> {
+        self.timeouts.header_read = timeout("header_read_timeout", value)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_keep_alive_timeout(&self) -> Option<f64> {
+        self.timeouts
+            .keep_alive
+            .map(|timeout| timeout.as_secs_f64())
+    }
+
+    /// How long an idle connection is kept open for another request. Zero
+    /// disables keep-alive.
+    #[setter]
+    fn set_keep_alive_timeout(&mut self, value: Option<f64>) -> PyResult<()> {
+        self.timeouts.keep_alive = timeout("keep_alive_timeout", value)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_body_read_timeout(&self) -> Option<f64> {
+        self.http
+            .body_read_timeout
+            .map(|timeout| timeout.as_secs_f64())
+    }
+
+    /// How long a client has to send a request's whole body, not counting
+    /// time that the app takes to ask for more of it.
+    #[setter]
+    fn set_body_read_timeout(&mut self, value: Option<f64>) -> PyResult<()> {
+        self.http.body_read_timeout = timeout("body_read_timeout", value)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_response_timeout(&self) -> Option<f64> {
+        self.http
+            .response_timeout
+            .map(|timeout| timeout.as_secs_f64())
+    }
+
+    /// How long the app has to start its response, before the server
+    /// answers 504 and cancels it.
+    #[setter]
+    fn set_response_timeout(&mut self, value: Option<f64>) -> PyResult<()> {
+        self.http.response_timeout = timeout("response_timeout", value)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_min_request_body_rate(&self) -> Option<u64> {
+        self.min_request_body_rate
+    }
+
+    /// The bytes per second that clients have to send request bodies at,
+    /// give or take the grace period.
+    #[setter]
+    fn set_min_request_body_rate(&mut self, value: Option<u64>) -> PyResult<()> {
+        self.min_request_body_rate = data_rate("min_request_body_rate", value)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_min_response_rate(&self) -> Option<u64> {
+        self.min_response_rate
+    }
+
+    /// The bytes per second that clients have to take responses at, give
+    /// or take the grace period.
+    #[setter]
+    fn set_min_response_rate(&mut self, value: Option<u64>) -> PyResult<()> {
+        self.min_response_rate = data_rate("min_response_rate", value)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_min_rate_grace_period(&self) -> f64 {
+        self.min_rate_grace_period.as_secs_f64()
+    }
+
+    /// How far behind the minimum rates clients may fall, as time spent
+    /// waiting on them.
+    #[setter]
+    fn set_min_rate_grace_period(&mut self, value: f64) -> PyResult<()> {
+        self.min_rate_grace_period =
+            timeout("min_rate_grace_period", Some(value))?.unwrap_or_default();
+        Ok(())
+    }
+
+    #[getter]
+    fn get_max_connections(&self) -> Option<usize> {
+        self.limits.max_connections
+    }
+
+    /// How many connections each server process keeps open at once.
+    #[setter]
+    fn set_max_connections(&mut self, value: Option<usize>) -> PyResult<()> {
+        self.limits.max_connections = limit("max_connections", value)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_max_concurrent_requests(&self) -> Option<usize> {
+        self.limits.max_concurrent_requests
+    }
+
+    /// How many requests each server process handles at once, before it
+    /// answers 503.
+    #[setter]
+    fn set_max_concurrent_requests(&mut self, value: Option<usize>) -> PyResult<()> {
+        self.limits.max_concurrent_requests = limit("max_concurrent_requests", value)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_request_queue_timeout(&self) -> Option<f64> {
+        self.limits
+            .request_queue_timeout
+            .map(|timeout| timeout.as_secs_f64())
+    }
+
+    /// How long a request over max_concurrent_requests waits for another to
+    /// finish, before it is answered with 503.
+    #[setter]
+    fn set_request_queue_timeout(&mut self, value: Option<f64>) -> PyResult<()> {
+        self.limits.request_queue_timeout = timeout("request_queue_timeout", value)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_rate_limit(&self) -> Option<f64> {
+        self.rate_limit
+    }
+
+    /// The requests per second that each client IP address may make, on
+    /// average, before it is answered with 429.
+    #[setter]
+    fn set_rate_limit(&mut self, value: Option<f64>) -> PyResult<()> {
+        if let Some(per_second) = value {
+            rate_limit("rate_limit", per_second, 1)?;
+        }
+        self.rate_limit = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_rate_limit_burst(&self) -> Option<u32> {
+        self.rate_limit_burst
+    }
+
+    /// How many requests a client may make at once under the rate limit;
+    /// by default, a second's worth.
+    #[setter]
+    fn set_rate_limit_burst(&mut self, value: Option<u32>) -> PyResult<()> {
+        if let Some(burst) = value {
+            rate_limit("rate_limit_burst", 1.0, burst)?;
+        }
+        self.rate_limit_burst = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_path_rate_limits(&self) -> BTreeMap<String, (f64, u32)> {
+        self.path_rate_limits.clone()
+    }
+
+    /// Rate limits, as requests per second and bursts, for paths starting
+    /// with each prefix, which replace the global rate limit for them.
+    #[setter]
+    fn set_path_rate_limits(&mut self, value: BTreeMap<String, (f64, u32)>) -> PyResult<()> {
+        for (prefix, (per_second, burst)) in &value {
+            rate_limit(
+                &format!("path_rate_limits[{:?}]", prefix),
+                *per_second,
+                *burst,
+            )?;
+        }
+        self.path_rate_limits = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_forwarded_allow_ips(&self) -> Vec<String> {
+        self.proxy
+            .forwarded_allow_ips
+            .iter()
+            .map(TrustedProxy::to_string)
+            .collect()
+    }
+
+    /// Proxies whose Forwarded and X-Forwarded-* headers are trusted, as IP
+    /// addresses, networks such as "10.0.0.0/8", "unix" or "*".
+    #[setter]
+    fn set_forwarded_allow_ips(&mut self, value: Vec<String>) -> PyResult<()> {
+        self.proxy.forwarded_allow_ips = value
+            .iter()
+            .map(|proxy| proxy.parse())
+            .collect::<Result<_, _>>()
+            .map_err(PyValueError::new_err)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_proxy_protocol(&self) -> bool {
+        self.proxy.proxy_protocol
+    }
+
+    /// Whether every connection starts with a PROXY protocol header, whose
+    /// source address is used as the client's. Connections without one are
+    /// closed, so only enable it behind a load balancer that sends it.
+    #[setter]
+    fn set_proxy_protocol(&mut self, value: bool) -> PyResult<()> {
+        self.proxy.proxy_protocol = value;
+        Ok(())
+    }
+
+    #[getter]
+    fn get_root_path(&self) -> String {
+        self.proxy.root_path.clone()
+    }
+
+    /// The path the app is mounted under, such as "/svc/api", which is
+    /// given to it as `root_path` and added to the start of `path`.
+    #[setter]
+    fn set_root_path(&mut self, value: &str) -> PyResult<()> {
+        if !value.is_empty() && !value.starts_with('/') {
+            return Err(PyValueError::new_err(format!(
+                "root_path must start with '/', not '{}'",
+                value
+            )));
+        }
+        self.proxy.root_path = value.trim_end_matches('/').to_string();
+        Ok(())
+    }
+
+    #[getter]
+    fn get_root_path_included(&self) -> bool {
+        self.proxy.root_path_included
+    }
+
+    /// Whether request paths already start with the root path, for gateways
+    /// that don't strip it, so that it isn't added to them again.
+    #[setter]
+    fn set_root_path_included(&mut self, value: bool) -> PyResult<()> {
+        self.proxy.root_path_included = value;
+        Ok(())
+    }
 }
 
 #[pyproto]
@@ -58,21 +727,165 @@ impl Clone for Settings {
     fn clone(&self) -> Self {
         Self {
             host: self.host.clone(),
-            port: self.port.clone(),
+            port: self.port,
+            lifespan: self.lifespan,
+            tls: self.tls.clone(),
+            bind: self.bind.clone(),
+            uds: self.uds.clone(),
+            unix_socket: self.unix_socket.clone(),
+            fd: self.fd,
+            socket_activation: self.socket_activation,
+            shutdown: self.shutdown.clone(),
+            workers: self.workers,
+            worker: self.worker.clone(),
+            max_memory: self.max_memory,
+            reload: self.reload,
+            reload_dirs: self.reload_dirs.clone(),
+            reload_includes: self.reload_includes.clone(),
+            reload_excludes: self.reload_excludes.clone(),
+            http: self.http.clone(),
+            timeouts: self.timeouts.clone(),
+            min_request_body_rate: self.min_request_body_rate,
+            min_response_rate: self.min_response_rate,
+            min_rate_grace_period: self.min_rate_grace_period,
+            limits: self.limits.clone(),
+            rate_limit: self.rate_limit,
+            rate_limit_burst: self.rate_limit_burst,
+            path_rate_limits: self.path_rate_limits.clone(),
+            proxy: self.proxy.clone(),
+        }
+    }
+}
+
+impl From<&Settings> for LifespanMode {
+    fn from(settings: &Settings) -> Self {
+        settings.lifespan
+    }
+}
+
+impl From<&Settings> for TlsConfig {
+    fn from(settings: &Settings) -> Self {
+        settings.tls.clone()
+    }
+}
+
+impl From<&Settings> for ShutdownConfig {
+    fn from(settings: &Settings) -> Self {
+        settings.shutdown.clone()
+    }
+}
+
+#[cfg(unix)]
+impl From<&Settings> for SupervisorConfig {
+    fn from(settings: &Settings) -> Self {
+        SupervisorConfig {
+            workers: settings.workers,
+            max_memory: settings
+                .max_memory
+                .map(|mib| mib.saturating_mul(1024 * 1024)),
+            reload: if settings.reload {
+                Some(ReloadConfig::from(settings))
+            } else {
+                None
+            },
         }
     }
 }
 
-impl From<&Settings> for Builder<AddrIncoming> {
+#[cfg(unix)]
+impl From<&Settings> for ReloadConfig {
     fn from(settings: &Settings) -> Self {
-        let addr = SocketAddr::from(settings);
-        Server::bind(&addr)
+        ReloadConfig {
+            dirs: settings.reload_dirs.iter().map(PathBuf::from).collect(),
+            includes: settings.reload_includes.clone(),
+            excludes: settings.reload_excludes.clone(),
+        }
     }
 }
 
-impl From<&Settings> for SocketAddr {
+impl From<&Settings> for HttpConfig {
     fn from(settings: &Settings) -> Self {
-        SocketAddr::from((settings.host, settings.port))
+        HttpConfig {
+            min_request_body_rate: settings.min_request_body_rate.map(|bytes_per_second| {
+                MinDataRate {
+                    bytes_per_second,
+                    grace_period: settings.min_rate_grace_period,
+                }
+            }),
+            ..settings.http.clone()
+        }
+    }
+}
+
+impl From<&Settings> for TimeoutConfig {
+    fn from(settings: &Settings) -> Self {
+        TimeoutConfig {
+            min_response_rate: settings
+                .min_response_rate
+                .map(|bytes_per_second| MinDataRate {
+                    bytes_per_second,
+                    grace_period: settings.min_rate_grace_period,
+                }),
+            ..settings.timeouts.clone()
+        }
+    }
+}
+
+impl From<&Settings> for LimitConfig {
+    fn from(settings: &Settings) -> Self {
+        settings.limits.clone()
+    }
+}
+
+impl From<&Settings> for RateLimitConfig {
+    fn from(settings: &Settings) -> Self {
+        RateLimitConfig {
+            global: settings.rate_limit.map(|per_second| RateLimit {
+                per_second,
+                burst: settings
+                    .rate_limit_burst
+                    .unwrap_or_else(|| per_second.ceil() as u32),
+            }),
+            paths: settings
+                .path_rate_limits
+                .iter()
+                .map(|(prefix, &(per_second, burst))| {
+                    (prefix.clone(), RateLimit { per_second, burst })
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&Settings> for ProxyConfig {
+    fn from(settings: &Settings) -> Self {
+        settings.proxy.clone()
+    }
+}
+
+impl From<&Settings> for WorkerConfig {
+    fn from(settings: &Settings) -> Self {
+        settings.worker.clone()
+    }
+}
+
+impl From<&Settings> for BindConfig {
+    fn from(settings: &Settings) -> Self {
+        let mut targets = settings.bind.clone();
+        targets.extend(settings.fd.map(BindTarget::Fd));
+        if settings.socket_activation {
+            targets.push(BindTarget::SocketActivation);
+        }
+        targets.extend(settings.uds.clone().map(BindTarget::Unix));
+
+        if targets.is_empty() {
+            targets.push(BindTarget::Tcp(settings.host.clone(), settings.port));
+        }
+
+        BindConfig {
+            targets,
+            uds: settings.unix_socket.clone(),
+        }
     }
 }
 
@@ -80,17 +893,121 @@ impl fmt::Debug for Settings {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Settings {{ host = '{:?}', port = {:?} }}",
-            self.host, self.port
+            "Settings {{ host = '{}', port = {:?}, bind = {:?}, lifespan = '{}', tls = {:?}, uds = {:?}, unix_socket = {:?}, fd = {:?}, socket_activation = {:?}, shutdown = {:?}, workers = {:?}, worker = {:?}, max_memory = {:?}, reload = {:?}, reload_dirs = {:?}, reload_includes = {:?}, reload_excludes = {:?}, http = {:?}, timeouts = {:?}, min_request_body_rate = {:?}, min_response_rate = {:?}, min_rate_grace_period = {:?}, limits = {:?}, rate_limit = {:?}, rate_limit_burst = {:?}, path_rate_limits = {:?}, proxy = {:?} }}",
+            self.host,
+            self.port,
+            self.bind,
+            self.lifespan,
+            self.tls,
+            self.uds,
+            self.unix_socket,
+            self.fd,
+            self.socket_activation,
+            self.shutdown,
+            self.workers,
+            self.worker,
+            self.max_memory,
+            self.reload,
+            self.reload_dirs,
+            self.reload_includes,
+            self.reload_excludes,
+            self.http,
+            self.timeouts,
+            self.min_request_body_rate,
+            self.min_response_rate,
+            self.min_rate_grace_period,
+            self.limits,
+            self.rate_limit,
+            self.rate_limit_burst,
+            self.path_rate_limits,
+            self.proxy
         )
     }
 }
 
+/// Converts a timeout setting given in seconds.
+fn timeout(name: &str, seconds: Option<f64>) -> PyResult<Option<Duration>> {
+    match seconds {
+        Some(seconds) if seconds >= 0.0 && seconds.is_finite() => {
+            Ok(Some(Duration::from_secs_f64(seconds)))
+        }
+        Some(_) => Err(PyValueError::new_err(format!(
+            "{} must be a non-negative number of seconds",
+            name
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Checks a limit on how many of something there can be at once.
+fn limit(name: &str, value: Option<usize>) -> PyResult<Option<usize>> {
+    match value {
+        Some(0) => Err(PyValueError::new_err(format!(
+            "{} must be at least 1",
+            name
+        ))),
+        value => Ok(value),
+    }
+}
+
+/// Checks a rate limit, given as requests per second and a burst size.
+fn rate_limit(name: &str, per_second: f64, burst: u32) -> PyResult<()> {
+    if !(per_second > 0.0 && per_second.is_finite()) {
+        return Err(PyValueError::new_err(format!(
+            "{} must allow a positive number of requests per second",
+            name
+        )));
+    }
+    if burst == 0 {
+        return Err(PyValueError::new_err(format!(
+            "{} must allow bursts of at least 1 request",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Checks a minimum data rate setting given in bytes per second.
+fn data_rate(name: &str, bytes_per_second: Option<u64>) -> PyResult<Option<u64>> {
+    match bytes_per_second {
+        Some(0) => Err(PyValueError::new_err(format!(
+            "{} must be at least one byte per second",
+            name
+        ))),
+        bytes_per_second => Ok(bytes_per_second),
+    }
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Settings {
-            host: IpAddr::from([127, 0, 0, 1]),
+            host: "127.0.0.1".to_string(),
             port: 3000,
+            lifespan: LifespanMode::Auto,
+            tls: TlsConfig::default(),
+            bind: Vec::new(),
+            uds: None,
+            unix_socket: UnixSocketConfig::default(),
+            fd: None,
+            socket_activation: false,
+            shutdown: ShutdownConfig::default(),
+            workers: 1,
+            worker: WorkerConfig::default(),
+            max_memory: None,
+            reload: false,
+            reload_dirs: Vec::new(),
+            reload_includes: Vec::new(),
+            reload_excludes: Vec::new(),
+            http: HttpConfig::default(),
+            timeouts: TimeoutConfig::default(),
+            min_request_body_rate: None,
+            min_response_rate: None,
+            min_rate_grace_period: DEFAULT_MIN_RATE_GRACE_PERIOD,
+            limits: LimitConfig::default(),
+            rate_limit: None,
+            rate_limit_burst: None,
+            path_rate_limits: BTreeMap::new(),
+            proxy: ProxyConfig::default(),
         }
     }
 }
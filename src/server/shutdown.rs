@@ -0,0 +1,236 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Arc,
+    sync::OnceLock,
+    time::Duration,
+};
+
+use futures::{channel::mpsc, future, StreamExt};
+use pyo3::{exceptions::PyValueError, PyResult};
+use tokio::sync::{broadcast, watch, Notify};
+
+/// How long a graceful shutdown waits for open connections by default.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the server winds down once it has been asked to stop.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShutdownConfig {
+    /// How long to wait for in-flight requests before closing their
+    /// connections, or `None` to wait for as long as they take.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            timeout: Some(DEFAULT_SHUTDOWN_TIMEOUT),
+        }
+    }
+}
+
+/// Converts a shutdown timeout given in seconds.
+pub fn shutdown_timeout(seconds: f64) -> PyResult<Duration> {
+    if seconds >= 0.0 && seconds.is_finite() {
+        Ok(Duration::from_secs_f64(seconds))
+    } else {
+        Err(PyValueError::new_err(
+            "shutdown timeout must be a non-negative number of seconds",
+        ))
+    }
+}
+
+/// A request to shut the server down gracefully.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShutdownRequest {
+    /// Overrides the configured shutdown timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl ShutdownRequest {
+    /// How long to wait for open connections, given the server's config.
+    pub fn timeout(&self, config: &ShutdownConfig) -> Option<Duration> {
+        self.timeout.or(config.timeout)
+    }
+}
+
+/// Requests made from Python, which go to every server in the process the
+/// same way a signal would.
+fn python_requests() -> &'static broadcast::Sender<ShutdownRequest> {
+    static PYTHON_REQUESTS: OnceLock<broadcast::Sender<ShutdownRequest>> = OnceLock::new();
+    PYTHON_REQUESTS.get_or_init(|| broadcast::channel(1).0)
+}
+
+/// Ask every running server to shut down gracefully.
+pub fn request_shutdown(request: ShutdownRequest) {
+    // There's nothing to do if no server is running.
+    python_requests().send(request).unwrap_or(0);
+}
+
+/// The shutdown requests received by a server: SIGINT, SIGTERM, a call to
+/// `request_shutdown`, or a request sent to this server alone.
+pub struct ShutdownRequests {
+    /// Whether SIGINT and SIGTERM are listened for, which installs handlers
+    /// for them in place of Python's.
+    signals: bool,
+    #[cfg(unix)]
+    terminate: Option<tokio::signal::unix::Signal>,
+    python: Option<broadcast::Receiver<ShutdownRequest>>,
+    server: Option<mpsc::UnboundedReceiver<ShutdownRequest>>,
+}
+
+impl ShutdownRequests {
+    /// The requests for a server that owns the process: signals and calls
+    /// to `request_shutdown`.
+    pub fn new() -> Self {
+        ShutdownRequests {
+            signals: true,
+            #[cfg(unix)]
+            terminate: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .ok(),
+            python: Some(python_requests().subscribe()),
+            server: None,
+        }
+    }
+
+    /// The requests sent to a server alone, which is run by an app that
+    /// handles signals itself.
+    pub fn from_server(requests: mpsc::UnboundedReceiver<ShutdownRequest>) -> Self {
+        ShutdownRequests {
+            signals: false,
+            #[cfg(unix)]
+            terminate: None,
+            python: None,
+            server: Some(requests),
+        }
+    }
+
+    /// Resolves with the next request.
+    pub async fn next(&mut self) -> ShutdownRequest {
+        #[cfg(unix)]
+        let terminate = terminate(&mut self.terminate);
+        #[cfg(not(unix))]
+        let terminate = future::pending::<()>();
+
+        tokio::select! {
+            _ = interrupt(), if self.signals => ShutdownRequest::default(),
+            _ = terminate => ShutdownRequest::default(),
+            request = python_request(&mut self.python) => request,
+            request = server_request(&mut self.server) => request,
+        }
+    }
+}
+
+async fn interrupt() {
+    if tokio::signal::ctrl_c().await.is_err() {
+        future::pending::<()>().await
+    }
+}
+
+#[cfg(unix)]
+async fn terminate(signal: &mut Option<tokio::signal::unix::Signal>) {
+    if let Some(signal) = signal {
+        if signal.recv().await.is_some() {
+            return;
+        }
+    }
+    future::pending::<()>().await
+}
+
+async fn python_request(
+    requests: &mut Option<broadcast::Receiver<ShutdownRequest>>,
+) -> ShutdownRequest {
+    if let Some(requests) = requests {
+        match requests.recv().await {
+            Ok(request) => return request,
+            Err(broadcast::error::RecvError::Lagged(_)) => return ShutdownRequest::default(),
+            Err(broadcast::error::RecvError::Closed) => {}
+        }
+    }
+    future::pending().await
+}
+
+async fn server_request(
+    requests: &mut Option<mpsc::UnboundedReceiver<ShutdownRequest>>,
+) -> ShutdownRequest {
+    if let Some(requests) = requests {
+        if let Some(request) = requests.next().await {
+            return request;
+        }
+    }
+    future::pending().await
+}
+
+/// Spawns the tasks that serve connections, including the WebSocket
+/// sessions that take connections over from hyper, so that any still
+/// running when the shutdown timeout runs out can be closed.
+#[derive(Clone)]
+pub struct ConnectionTasks {
+    close: Arc<watch::Sender<bool>>,
+    closed: watch::Receiver<bool>,
+    running: Arc<RunningTasks>,
+}
+
+#[derive(Default)]
+struct RunningTasks {
+    count: AtomicUsize,
+    /// Notified when the last task finishes.
+    finished: Notify,
+}
+
+/// Counts a task as running until it is dropped.
+struct TaskGuard(Arc<RunningTasks>);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.finished.notify_one();
+        }
+    }
+}
+
+impl ConnectionTasks {
+    pub fn new() -> Self {
+        let (close, closed) = watch::channel(false);
+        ConnectionTasks {
+            close: Arc::new(close),
+            closed,
+            running: Arc::default(),
+        }
+    }
+
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.running.count.fetch_add(1, Ordering::SeqCst);
+        let guard = TaskGuard(self.running.clone());
+        let mut closed = self.closed.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = task => {}
+                Ok(_) = closed.changed() => {}
+            }
+            drop(guard);
+        });
+    }
+
+    /// Resolves once every task has finished.
+    pub async fn wait_all(&self) {
+        // A notification sent before this waits is kept for it
+        while self.running.count.load(Ordering::SeqCst) > 0 {
+            self.running.finished.notified().await;
+        }
+    }
+
+    /// Drop every task, which closes their connections.
+    pub fn close_all(&self) {
+        self.close.send(true).unwrap_or(());
+    }
+}
+
+impl<F> hyper::rt::Executor<F> for ConnectionTasks
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    fn execute(&self, task: F) {
+        self.spawn(task);
+    }
+}
@@ -0,0 +1,483 @@
+use std::{
+    collections::HashMap,
+    io,
+    os::unix::io::{AsRawFd, FromRawFd, OwnedFd},
+    path::PathBuf,
+    process::ExitStatus,
+    time::{Duration, Instant},
+};
+
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use pyo3::{PyResult, Python};
+use tokio::{
+    io::AsyncReadExt,
+    net::UnixStream,
+    process::{Child, Command},
+    signal::unix::{signal, Signal, SignalKind},
+};
+
+use super::{
+    listener::{BindConfig, Listener},
+    reloader::{ReloadConfig, Reloader},
+    worker::{LINK_FD_ENV, READY, RETIRE},
+    Settings,
+};
+
+/// The first file descriptor that workers find the listening sockets at.
+const FIRST_LISTEN_FD: i32 = 3;
+
+/// The environment variable telling workers how many listening sockets
+/// they have inherited.
+const LISTEN_FDS_ENV: &str = "ASGI_SERVER_LISTEN_FDS";
+
+/// Workers that exit sooner than this after starting are restarted after
+/// a delay, so that an app that fails on import doesn't spin the CPU.
+const MIN_WORKER_LIFETIME: Duration = Duration::from_secs(1);
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// How often the memory use of workers is checked.
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How the supervisor runs its workers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SupervisorConfig {
+    pub workers: usize,
+    /// The resident memory, in bytes, that a worker may use before it is
+    /// replaced.
+    pub max_memory: Option<u64>,
+    /// Which source files to watch, replacing the workers when they change.
+    pub reload: Option<ReloadConfig>,
+}
+
+/// Bind the server's sockets, then run `workers` copies of `worker_command`
+/// which serve connections on them. Workers that exit are restarted until
+/// the supervisor is asked to shut down, which it passes on to them.
+/// SIGHUP or SIGUSR2 replaces every worker, without closing the sockets, as
+/// does changing a source file when reloading is enabled.
+pub async fn start_supervisor(
+    settings: pyo3::Py<Settings>,
+    worker_command: Vec<String>,
+) -> PyResult<()> {
+    let settings = Python::with_gil(|py| {
+        let settings: &Settings = &settings.borrow(py);
+        settings.clone()
+    });
+    let config = SupervisorConfig::from(&settings);
+
+    log::info!("Starting asgi-server 0.0.1 supervisor");
+    let listeners = match BindConfig::from(&settings).bind().await {
+        Ok(listeners) => listeners,
+        Err(io_err) => {
+            log::error!("Could not bind to {}", io_err);
+            return Err(io_err.into());
+        }
+    };
+    let mut supervisor = Supervisor::new(config, worker_command, &listeners)?;
+    supervisor.run().await?;
+    Ok(())
+}
+
+enum Event {
+    Exited(usize, io::Result<ExitStatus>),
+    /// Start a worker, which replaces the given one once it is ready.
+    Start(Option<usize>),
+    Message(usize, u8, UnixStream),
+    /// A worker closed its link, which happens when it exits.
+    LinkClosed(usize),
+}
+
+struct Worker {
+    pid: u32,
+    started: Instant,
+    /// Whether the worker is serving connections.
+    ready: bool,
+    /// The worker that this one takes over from once it is ready.
+    replaces: Option<usize>,
+    /// Whether a replacement for this worker has been started.
+    replaced: bool,
+}
+
+struct Supervisor {
+    config: SupervisorConfig,
+    worker_command: Vec<String>,
+    /// Duplicates of the listening sockets, numbered so that they don't
+    /// collide with the descriptors that workers get them at.
+    listen_fds: Vec<OwnedFd>,
+    workers: HashMap<usize, Worker>,
+    next_worker: usize,
+    events: FuturesUnordered<BoxFuture<'static, Event>>,
+    /// Workers that failed to start while reloading is enabled, which are
+    /// started again once a file changes, given what they were replacing.
+    waiting_for_changes: Vec<Option<usize>>,
+    shutting_down: bool,
+}
+
+impl Supervisor {
+    fn new(
+        config: SupervisorConfig,
+        worker_command: Vec<String>,
+        listeners: &[Listener],
+    ) -> io::Result<Self> {
+        if worker_command.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No worker command was given",
+            ));
+        }
+
+        if config.max_memory.is_some() && !cfg!(target_os = "linux") {
+            log::warn!("Limiting the memory of workers is not supported on this platform");
+        }
+
+        let listen_fds = listeners
+            .iter()
+            .map(|listener| duplicate_above(&listener.try_clone_fd()?, link_fd(listeners.len())))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Supervisor {
+            config,
+            worker_command,
+            listen_fds,
+            workers: HashMap::new(),
+            next_worker: 0,
+            events: FuturesUnordered::new(),
+            waiting_for_changes: Vec::new(),
+            shutting_down: false,
+        })
+    }
+
+    async fn run(&mut self) -> io::Result<()> {
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut reload_tls = signal(SignalKind::user_defined1())?;
+        let mut hangup = signal(SignalKind::hangup())?;
+        let mut reload = signal(SignalKind::user_defined2())?;
+        let mut memory_checks = tokio::time::interval_at(
+            tokio::time::Instant::now() + MEMORY_CHECK_INTERVAL,
+            MEMORY_CHECK_INTERVAL,
+        );
+        let mut reloader = self.config.reload.as_ref().map(Reloader::new);
+
+        for _ in 0..self.config.workers {
+            self.spawn_worker(None)?;
+        }
+
+        while !(self.shutting_down && self.workers.is_empty()) {
+            tokio::select! {
+                Some(event) = self.events.next() => self.handle_event(event),
+                _ = recv(&mut interrupt) => self.shutdown(),
+                _ = recv(&mut terminate) => self.shutdown(),
+                _ = recv(&mut reload_tls) => self.signal_workers(libc::SIGUSR1),
+                _ = recv(&mut hangup) => self.reload(),
+                _ = recv(&mut reload) => self.reload(),
+                _ = memory_checks.tick(), if self.config.max_memory.is_some() => {
+                    self.check_memory()
+                }
+                changed = next_change(&mut reloader) => {
+                    log::info!("Detected a change in {}; reloading", changed.display());
+                    self.reload();
+                }
+            }
+        }
+
+        log::info!("Shutdown complete");
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Exited(id, status) => {
+                let worker = match self.workers.remove(&id) {
+                    Some(worker) => worker,
+                    None => return,
+                };
+                match status {
+                    Ok(status) if self.shutting_down || worker.replaced => {
+                        log::info!("Worker {} exited ({})", worker.pid, status)
+                    }
+                    Ok(status) => {
+                        log::error!("Worker {} exited unexpectedly ({})", worker.pid, status)
+                    }
+                    Err(io_err) => {
+                        log::error!("Could not wait for worker {}: {}", worker.pid, io_err)
+                    }
+                }
+
+                if self.shutting_down || worker.replaced {
+                    return;
+                }
+                if self.config.reload.is_some() && !worker.ready {
+                    log::warn!("Waiting for a file to change before starting another worker");
+                    self.waiting_for_changes.push(worker.replaces);
+                    return;
+                }
+                if worker.started.elapsed() < MIN_WORKER_LIFETIME {
+                    self.start_later(worker.replaces);
+                } else {
+                    self.start_worker(worker.replaces);
+                }
+            }
+            Event::Start(replaces) => {
+                if !self.shutting_down {
+                    self.start_worker(replaces);
+                }
+            }
+            Event::Message(id, message, link) => {
+                self.handle_message(id, message);
+                self.events.push(receive_message(id, link).boxed());
+            }
+            Event::LinkClosed(id) => {
+                if let Some(worker) = self.workers.get(&id) {
+                    log::debug!("Worker {} closed its link", worker.pid);
+                }
+            }
+        }
+    }
+
+    fn handle_message(&mut self, id: usize, message: u8) {
+        match message {
+            READY => {
+                let mut replaces = match self.workers.get_mut(&id) {
+                    Some(worker) => {
+                        worker.ready = true;
+                        worker.replaces.take()
+                    }
+                    None => return,
+                };
+                // A reload can replace a worker that was itself still waiting
+                // to take over, in which case both are retired.
+                while let Some(old_worker) =
+                    replaces.and_then(|old_id| self.workers.get_mut(&old_id))
+                {
+                    log::info!("Retiring worker {}", old_worker.pid);
+                    signal_worker(old_worker.pid, libc::SIGTERM);
+                    replaces = old_worker.replaces.take();
+                }
+            }
+            RETIRE => self.replace_worker(id),
+            _ => log::warn!("Unexpected message from worker: {:?}", message),
+        }
+    }
+
+    /// Start a replacement for a worker, which retires it once it is ready
+    /// to take over.
+    fn replace_worker(&mut self, id: usize) {
+        match self.workers.get_mut(&id) {
+            Some(worker) if !worker.replaced && !self.shutting_down => worker.replaced = true,
+            _ => return,
+        }
+        self.start_worker(Some(id));
+    }
+
+    /// Replace every worker with one started afresh, which imports the app
+    /// again. The old workers keep serving until their replacements are
+    /// ready, and are then drained.
+    fn reload(&mut self) {
+        if self.shutting_down {
+            return;
+        }
+        log::info!("Reloading workers");
+        let mut current: Vec<usize> = self
+            .workers
+            .iter()
+            .filter(|(_, worker)| !worker.replaced)
+            .map(|(id, _)| *id)
+            .collect();
+        current.sort_unstable();
+        for id in current {
+            self.replace_worker(id);
+        }
+        for replaces in std::mem::take(&mut self.waiting_for_changes) {
+            self.start_worker(replaces);
+        }
+    }
+
+    fn check_memory(&mut self) {
+        let max_memory = match self.config.max_memory {
+            Some(max_memory) => max_memory,
+            None => return,
+        };
+
+        let over_limit: Vec<usize> = self
+            .workers
+            .iter()
+            .filter(|(_, worker)| worker.ready && !worker.replaced)
+            .filter_map(|(id, worker)| {
+                let memory = resident_memory(worker.pid)?;
+                if memory > max_memory {
+                    log::warn!(
+                        "Worker {} is using {} MiB of memory, over the limit of {} MiB",
+                        worker.pid,
+                        memory / (1024 * 1024),
+                        max_memory / (1024 * 1024)
+                    );
+                    Some(*id)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for id in over_limit {
+            self.replace_worker(id);
+        }
+    }
+
+    fn start_worker(&mut self, replaces: Option<usize>) {
+        if let Err(io_err) = self.spawn_worker(replaces) {
+            log::error!("Could not start worker: {}", io_err);
+            self.start_later(replaces);
+        }
+    }
+
+    fn start_later(&mut self, replaces: Option<usize>) {
+        self.events.push(
+            async move {
+                tokio::time::sleep(RESTART_DELAY).await;
+                Event::Start(replaces)
+            }
+            .boxed(),
+        );
+    }
+
+    fn spawn_worker(&mut self, replaces: Option<usize>) -> io::Result<()> {
+        let child_link_fd = link_fd(self.listen_fds.len());
+        let (link, child_link) = std::os::unix::net::UnixStream::pair()?;
+        let child_link = duplicate_above(&child_link, child_link_fd)?;
+        link.set_nonblocking(true)?;
+        let link = UnixStream::from_std(link)?;
+
+        let listen_fds: Vec<i32> = self.listen_fds.iter().map(AsRawFd::as_raw_fd).collect();
+        let child_link_source = child_link.as_raw_fd();
+
+        let mut command = Command::new(&self.worker_command[0]);
+        command
+            .args(&self.worker_command[1..])
+            .env(LISTEN_FDS_ENV, listen_fds.len().to_string())
+            .env(LINK_FD_ENV, child_link_fd.to_string());
+        // SAFETY: only async-signal-safe functions are called between fork
+        // and exec
+        unsafe {
+            command.pre_exec(move || {
+                // The supervisor passes signals on itself, so workers are kept
+                // out of the terminal's process group.
+                if libc::setpgid(0, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                for (offset, fd) in listen_fds.iter().enumerate() {
+                    if libc::dup2(*fd, FIRST_LISTEN_FD + offset as i32) < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if libc::dup2(child_link_source, child_link_fd) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let mut child: Child = command.spawn()?;
+        drop(child_link);
+        let pid = child.id().unwrap_or(0);
+        let id = self.next_worker;
+        self.next_worker += 1;
+
+        match replaces.and_then(|old_id| self.workers.get(&old_id)) {
+            Some(old_worker) => log::info!("Started worker {} to replace {}", pid, old_worker.pid),
+            None => log::info!("Started worker {}", pid),
+        }
+        self.workers.insert(
+            id,
+            Worker {
+                pid,
+                started: Instant::now(),
+                ready: false,
+                replaces,
+                replaced: false,
+            },
+        );
+        self.events
+            .push(async move { Event::Exited(id, child.wait().await) }.boxed());
+        self.events.push(receive_message(id, link).boxed());
+        Ok(())
+    }
+
+    /// Ask the workers to shut down gracefully. Asking again makes them
+    /// close their connections straight away.
+    fn shutdown(&mut self) {
+        if self.shutting_down {
+            log::warn!("Shutting down immediately");
+        } else {
+            log::info!("Shutting down");
+            self.shutting_down = true;
+        }
+        self.signal_workers(libc::SIGTERM);
+    }
+
+    fn signal_workers(&self, signal: libc::c_int) {
+        for worker in self.workers.values() {
+            signal_worker(worker.pid, signal);
+        }
+    }
+}
+
+/// The descriptor that workers find their link to the supervisor at, after
+/// the listening sockets.
+fn link_fd(listen_fds: usize) -> i32 {
+    FIRST_LISTEN_FD + listen_fds as i32
+}
+
+/// Duplicate `fd` to a descriptor numbered above `fd_number`, so that it
+/// isn't overwritten when descriptors are moved into place for a worker.
+fn duplicate_above(fd: &impl AsRawFd, fd_number: i32) -> io::Result<OwnedFd> {
+    // SAFETY: `fd` stays open for the duration of this call
+    let high_fd = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, fd_number + 1) };
+    if high_fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `high_fd` was just opened, and nothing else owns it
+    Ok(unsafe { OwnedFd::from_raw_fd(high_fd) })
+}
+
+/// Wait for the next message from a worker. The link is dropped once the
+/// worker closes it, or it fails.
+async fn receive_message(id: usize, mut link: UnixStream) -> Event {
+    let mut message = [0u8];
+    match link.read(&mut message).await {
+        Ok(1) => Event::Message(id, message[0], link),
+        _ => Event::LinkClosed(id),
+    }
+}
+
+fn signal_worker(pid: u32, signal: libc::c_int) {
+    // SAFETY: `kill` has no memory safety requirements
+    unsafe { libc::kill(pid as libc::pid_t, signal) };
+}
+
+/// The resident memory of a process in bytes, where the platform makes it
+/// available.
+#[cfg(target_os = "linux")]
+fn resident_memory(pid: u32) -> Option<u64> {
+    let statm = std::fs::read_to_string(format!("/proc/{}/statm", pid)).ok()?;
+    let resident_pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    // SAFETY: `sysconf` has no memory safety requirements
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(resident_pages * page_size as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory(_pid: u32) -> Option<u64> {
+    None
+}
+
+async fn next_change(reloader: &mut Option<Reloader>) -> PathBuf {
+    match reloader {
+        Some(reloader) => reloader.next_change().await,
+        None => futures::future::pending().await,
+    }
+}
+
+async fn recv(signal: &mut Signal) {
+    if signal.recv().await.is_none() {
+        futures::future::pending::<()>().await
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::task::AtomicWaker;
+use hyper::body::{HttpBody, SizeHint};
+use tokio::time::{Instant, Sleep};
+
+/// How long a client has to send a request's headers by default.
+const DEFAULT_HEADER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long connections are kept open between requests by default.
+const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long connections may go without a request in progress.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeoutConfig {
+    /// How long a client has to send a request's headers, counted from when
+    /// the connection is accepted or the next request begins to arrive.
+    pub header_read: Option<Duration>,
+    /// How long a connection is kept open after a response, waiting for
+    /// another request.
+    pub keep_alive: Option<Duration>,
+    /// How quickly clients have to take the data that is written to them.
+    pub min_response_rate: Option<MinDataRate>,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig {
+            header_read: Some(DEFAULT_HEADER_READ_TIMEOUT),
+            keep_alive: Some(DEFAULT_KEEP_ALIVE_TIMEOUT),
+            min_response_rate: None,
+        }
+    }
+}
+
+/// Counts the requests in progress on a connection. A request is in
+/// progress from when its headers have been read until its response has
+/// been sent.
+#[derive(Clone, Default)]
+pub struct ActiveRequests(Arc<ActiveRequestsState>);
+
+#[derive(Default)]
+struct ActiveRequestsState {
+    count: AtomicUsize,
+    /// Whether the connection was taken over by another protocol, which
+    /// manages its own timeouts.
+    upgraded: AtomicBool,
+    /// Woken when the last request finishes.
+    idle: AtomicWaker,
+}
+
+impl ActiveRequests {
+    pub fn start(&self) -> RequestGuard {
+        self.0.count.fetch_add(1, Ordering::SeqCst);
+        RequestGuard(self.clone())
+    }
+
+    pub fn upgrade(&self) {
+        self.0.upgraded.store(true, Ordering::SeqCst);
+    }
+
+    fn is_active(&self) -> bool {
+        self.0.count.load(Ordering::SeqCst) > 0 || self.0.upgraded.load(Ordering::SeqCst)
+    }
+}
+
+/// Marks a request as in progress until it is dropped.
+pub struct RequestGuard(ActiveRequests);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        let ActiveRequests(state) = &self.0;
+        if state.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            state.idle.wake();
+        }
+    }
+}
+
+/// A response body which holds on to a guard, such as a `RequestGuard`
+/// that keeps its request in progress, until it has been sent.
+pub struct GuardedBody<B, G> {
+    body: B,
+    _guard: G,
+}
+
+impl<B, G> GuardedBody<B, G> {
+    pub fn new(body: B, guard: G) -> Self {
+        GuardedBody {
+            body,
+            _guard: guard,
+        }
+    }
+}
+
+impl<B: HttpBody + Unpin, G: Unpin> HttpBody for GuardedBody<B, G> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.get_mut().body).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.get_mut().body).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    /// Waiting for a request's headers.
+    ReadingHead,
+    /// Waiting for the next request to begin.
+    Idle,
+    /// Handling a request.
+    Active,
+}
+
+/// Closes a connection which takes too long to send a request's headers,
+/// or which stays idle for too long between requests.
+pub struct ConnectionTimer {
+    config: TimeoutConfig,
+    requests: ActiveRequests,
+    phase: Phase,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl ConnectionTimer {
+    /// A timer for a connection that was just accepted, which is expected
+    /// to send a request straight away.
+    pub fn new(config: &TimeoutConfig, requests: ActiveRequests) -> Self {
+        ConnectionTimer {
+            deadline: config
+                .header_read
+                .map(|timeout| Box::pin(tokio::time::sleep(timeout))),
+            config: config.clone(),
+            requests,
+            phase: Phase::ReadingHead,
+        }
+    }
+
+    /// Whether the connection has timed out, given whether data has just
+    /// been received on it. The task is woken when that might change.
+    pub fn poll_expired(&mut self, cx: &mut Context<'_>, received: bool) -> bool {
+        self.requests.0.idle.register(cx.waker());
+
+        let phase = if self.requests.is_active() {
+            Phase::Active
+        } else if received || self.phase == Phase::ReadingHead {
+            Phase::ReadingHead
+        } else {
+            Phase::Idle
+        };
+        if phase != self.phase {
+            self.phase = phase;
+            match phase {
+                Phase::ReadingHead => self.set_deadline(self.config.header_read),
+                Phase::Idle => self.set_deadline(self.config.keep_alive),
+                Phase::Active => self.set_deadline(None),
+            }
+        }
+
+        let expired = match &mut self.deadline {
+            Some(deadline) => deadline.as_mut().poll(cx).is_ready(),
+            None => false,
+        };
+        if expired {
+            match self.phase {
+                Phase::ReadingHead => log::debug!("Timed out reading request headers"),
+                _ => log::debug!("Closing idle connection"),
+            }
+        }
+        expired
+    }
+
+    fn set_deadline(&mut self, timeout: Option<Duration>) {
+        match (timeout, &mut self.deadline) {
+            (Some(timeout), Some(deadline)) => deadline.as_mut().reset(Instant::now() + timeout),
+            (Some(timeout), None) => self.deadline = Some(Box::pin(tokio::time::sleep(timeout))),
+            (None, _) => self.deadline = None,
+        }
+    }
+}
+
+/// A transfer rate which a client has to keep up, though it may fall behind
+/// it by up to a grace period. Only time spent waiting on the client counts
+/// against it, so a slow app doesn't make its clients look slow.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MinDataRate {
+    pub bytes_per_second: u64,
+    pub grace_period: Duration,
+}
+
+/// Keeps track of how quickly a client sends or takes data.
+#[derive(Debug)]
+pub struct DataRateMonitor {
+    rate: MinDataRate,
+    transferred: u64,
+    waited: Duration,
+}
+
+impl DataRateMonitor {
+    pub fn new(rate: MinDataRate) -> Self {
+        DataRateMonitor {
+            rate,
+            transferred: 0,
+            waited: Duration::ZERO,
+        }
+    }
+
+    pub fn rate(&self) -> MinDataRate {
+        self.rate
+    }
+
+    /// How much longer the client can be waited on before it falls below
+    /// the minimum rate.
+    pub fn remaining(&self) -> Duration {
+        let earned = Duration::try_from_secs_f64(
+            self.transferred as f64 / self.rate.bytes_per_second as f64,
+        )
+        .unwrap_or(Duration::MAX);
+        self.rate
+            .grace_period
+            .saturating_add(earned)
+            .saturating_sub(self.waited)
+    }
+
+    /// Record that `transferred` bytes were sent or taken by the client,
+    /// after waiting on it for `waited`.
+    pub fn record(&mut self, waited: Duration, transferred: u64) {
+        self.waited += waited;
+        self.transferred += transferred;
+    }
+}
+
+/// Closes a connection whose client takes the data written to it too
+/// slowly.
+pub struct WriteTimer {
+    monitor: DataRateMonitor,
+    /// When writes started waiting on the client, and when it will have
+    /// fallen below the minimum rate if they are still waiting.
+    stalled: Option<(Instant, Pin<Box<Sleep>>)>,
+}
+
+impl WriteTimer {
+    pub fn new(rate: MinDataRate) -> Self {
+        WriteTimer {
+            monitor: DataRateMonitor::new(rate),
+            stalled: None,
+        }
+    }
+
+    /// Whether the client has fallen below the minimum rate, given how many
+    /// bytes a write just passed to it, or that the write is waiting.
+    pub fn poll_expired(&mut self, cx: &mut Context<'_>, written: Poll<usize>) -> bool {
+        match written {
+            Poll::Ready(written) => {
+                let waited = match self.stalled.take() {
+                    Some((stalled_at, _)) => stalled_at.elapsed(),
+                    None => Duration::ZERO,
+                };
+                self.monitor.record(waited, written as u64);
+                false
+            }
+            Poll::Pending => {
+                let monitor = &self.monitor;
+                let (_, deadline) = self.stalled.get_or_insert_with(|| {
+                    let now = Instant::now();
+                    let deadline = tokio::time::sleep_until(now + monitor.remaining());
+                    (now, Box::pin(deadline))
+                });
+                if deadline.as_mut().poll(cx).is_ready() {
+                    log::debug!(
+                        "Closing connection which takes data slower than {} bytes per second",
+                        self.monitor.rate().bytes_per_second
+                    );
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::task::noop_waker_ref;
+    use hyper::Body;
+
+    use super::*;
+
+    const RATE: MinDataRate = MinDataRate {
+        bytes_per_second: 100,
+        grace_period: Duration::from_secs(2),
+    };
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn poll(timer: &mut WriteTimer, written: Poll<usize>) -> bool {
+        timer.poll_expired(&mut Context::from_waker(noop_waker_ref()), written)
+    }
+
+    /// A client that takes `bytes` once a second, after keeping each write
+    /// waiting for that second. Returns whether it fell below the rate.
+    async fn take(timer: &mut WriteTimer, bytes: usize, seconds: u32) -> bool {
+        for _ in 0..seconds {
+            if poll(timer, Poll::Pending) {
+                return true;
+            }
+            tokio::time::advance(SECOND).await;
+            if poll(timer, Poll::Pending) {
+                return true;
+            }
+            poll(timer, Poll::Ready(bytes));
+        }
+        false
+    }
+
+    #[test]
+    fn monitor_allows_the_grace_period_plus_what_was_transferred() {
+        let mut monitor = DataRateMonitor::new(RATE);
+        assert_eq!(monitor.remaining(), RATE.grace_period);
+        monitor.record(SECOND, 0);
+        assert_eq!(monitor.remaining(), RATE.grace_period - SECOND);
+        monitor.record(Duration::ZERO, 50);
+        assert_eq!(monitor.remaining(), RATE.grace_period - SECOND / 2);
+        monitor.record(RATE.grace_period, 0);
+        assert_eq!(monitor.remaining(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn client_that_takes_nothing_gets_the_grace_period() {
+        let mut timer = WriteTimer::new(RATE);
+        assert!(!poll(&mut timer, Poll::Pending));
+        tokio::time::advance(RATE.grace_period - Duration::from_millis(1)).await;
+        assert!(!poll(&mut timer, Poll::Pending));
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert!(poll(&mut timer, Poll::Pending));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn client_exactly_at_the_rate_is_kept() {
+        let mut timer = WriteTimer::new(RATE);
+        assert!(!take(&mut timer, 100, 60).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_client_falls_below_the_rate() {
+        let mut timer = WriteTimer::new(RATE);
+        // It falls behind by three quarters of a second each second, so it
+        // uses up the grace period during its third second.
+        assert!(!take(&mut timer, 25, 2).await);
+        assert!(take(&mut timer, 25, 1).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn time_not_spent_waiting_on_the_client_is_not_counted() {
+        let mut timer = WriteTimer::new(RATE);
+        tokio::time::advance(RATE.grace_period * 10).await;
+        assert!(!poll(&mut timer, Poll::Ready(0)));
+        assert!(!poll(&mut timer, Poll::Pending));
+        tokio::time::advance(RATE.grace_period - Duration::from_millis(1)).await;
+        assert!(!poll(&mut timer, Poll::Pending));
+    }
+
+    #[test]
+    fn guarded_body_keeps_its_request_in_progress_until_dropped() {
+        let requests = ActiveRequests::default();
+        let body = GuardedBody::new(Body::from("body"), requests.start());
+        assert!(requests.is_active());
+        drop(body);
+        assert!(!requests.is_active());
+    }
+}
@@ -0,0 +1,560 @@
+mod cert_resolver;
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader},
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use futures::future::{self, Either};
+use itertools::Itertools;
+use pyo3::{
+    exceptions::{PyOSError, PyValueError},
+    PyErr, PyResult,
+};
+use rustls::{
+    server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, NoClientAuth},
+    version::TLS13,
+    Certificate, PrivateKey, RootCertStore, ServerConfig, ServerConnection, SupportedCipherSuite,
+    SupportedProtocolVersion, ALL_CIPHER_SUITES,
+};
+use tokio_rustls::server::TlsStream;
+
+use self::cert_resolver::{CertResolver, ServerCert};
+use super::stream::Stream;
+use x509_parser::{
+    der_parser::asn1_rs::ToDer,
+    objects::{oid2sn, oid_registry},
+    x509::{AttributeTypeAndValue, X509Name},
+};
+
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&TLS13];
+
+/// The oldest TLS protocol version the server will negotiate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+impl FromStr for TlsVersion {
+    type Err = PyErr;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "1.2" => Ok(TlsVersion::Tls12),
+            "1.3" => Ok(TlsVersion::Tls13),
+            _ => Err(PyValueError::new_err(format!(
+                "'{}' is not a supported TLS version: expected '1.2' or '1.3'",
+                value
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TlsVersion::Tls12 => "1.2",
+            TlsVersion::Tls13 => "1.3",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Whether clients are asked for a certificate during the TLS handshake.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClientAuth {
+    /// Clients are not asked for a certificate.
+    None,
+    /// Clients may present a certificate, which must then be valid.
+    Optional,
+    /// Clients must present a valid certificate.
+    Required,
+}
+
+impl FromStr for ClientAuth {
+    type Err = PyErr;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(ClientAuth::None),
+            "optional" => Ok(ClientAuth::Optional),
+            "required" => Ok(ClientAuth::Required),
+            _ => Err(PyValueError::new_err(format!(
+                "'{}' is not a valid client auth mode: expected 'none', 'optional' or 'required'",
+                value
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ClientAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ClientAuth::None => "none",
+            ClientAuth::Optional => "optional",
+            ClientAuth::Required => "required",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// TLS options, as configured in `Settings`.
+///
+/// TLS is enabled when a `certfile` or any `sni_certs` are set.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// PEM file with the server certificate, optionally followed by its chain.
+    pub certfile: Option<String>,
+    /// PEM file with the private key of the server certificate.
+    pub keyfile: Option<String>,
+    /// PEM bundle of intermediate CA certificates, sent after the server
+    /// certificate.
+    pub ca_certs: Option<String>,
+    /// Cipher suites to offer, by their IANA names (colon separated).
+    pub ciphers: Option<String>,
+    pub min_version: TlsVersion,
+    /// PEM bundle of the CA certificates that client certificates are
+    /// verified against.
+    pub client_ca_certs: Option<String>,
+    pub client_auth: ClientAuth,
+    /// Certificates for specific hostnames, as `(certfile, keyfile)` pairs
+    /// keyed by hostname pattern. The `certfile` is served to any other
+    /// hostnames.
+    pub sni_certs: BTreeMap<String, (String, String)>,
+    /// How often to check the certificate and key files for changes, if at
+    /// all.
+    pub reload_interval: Option<Duration>,
+}
+
+impl TlsConfig {
+    /// Build an acceptor which terminates TLS on incoming connections, or
+    /// `None` if TLS is not enabled.
+    pub async fn acceptor(self) -> PyResult<Option<TlsAcceptor>> {
+        if self.certfile.is_none() && self.sni_certs.is_empty() {
+            return Ok(None);
+        }
+
+        let config = Arc::new(self);
+        let context = config.clone().load_context().await?;
+        Ok(Some(TlsAcceptor {
+            context: Arc::new(RwLock::new(Arc::new(context))),
+            config,
+        }))
+    }
+
+    /// Load the certificates and keys on a blocking thread.
+    async fn load_context(self: Arc<Self>) -> PyResult<TlsContext> {
+        tokio::task::spawn_blocking(move || self.context())
+            .await
+            .unwrap_or_else(|join_err| Err(PyOSError::new_err(join_err.to_string())))
+    }
+
+    fn context(&self) -> PyResult<TlsContext> {
+        let cert_resolver = Arc::new(self.cert_resolver()?);
+
+        let client_cert_verifier = match (self.client_auth, self.client_roots()?) {
+            (ClientAuth::None, _) => NoClientAuth::boxed(),
+            (ClientAuth::Optional, Some(roots)) => {
+                AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed()
+            }
+            (ClientAuth::Required, Some(roots)) => AllowAnyAuthenticatedClient::new(roots).boxed(),
+            (_, None) => {
+                return Err(PyValueError::new_err(
+                    "client_ca_certs are required to verify client certificates",
+                ))
+            }
+        };
+
+        let mut server_config = ServerConfig::builder()
+            .with_cipher_suites(&self.cipher_suites()?)
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(self.protocol_versions())
+            .map_err(|tls_err| PyValueError::new_err(format!("Invalid TLS options: {}", tls_err)))?
+            .with_client_cert_verifier(client_cert_verifier)
+            .with_cert_resolver(cert_resolver.clone());
+        server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(TlsContext {
+            acceptor: tokio_rustls::TlsAcceptor::from(Arc::new(server_config)),
+            cert_resolver,
+        })
+    }
+
+    /// The modification time and size of each file that certificates and
+    /// keys are loaded from.
+    fn file_stamps(&self) -> Vec<Option<(SystemTime, u64)>> {
+        let sni_files = self
+            .sni_certs
+            .values()
+            .flat_map(|(certfile, keyfile)| vec![certfile, keyfile]);
+
+        vec![
+            &self.certfile,
+            &self.keyfile,
+            &self.ca_certs,
+            &self.client_ca_certs,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(sni_files)
+        .map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            Some((metadata.modified().ok()?, metadata.len()))
+        })
+        .collect()
+    }
+
+    fn cert_resolver(&self) -> PyResult<CertResolver> {
+        let default_cert = match &self.certfile {
+            Some(certfile) => {
+                let keyfile = self
+                    .keyfile
+                    .as_ref()
+                    .ok_or_else(|| PyValueError::new_err("A keyfile is required to enable TLS"))?;
+                Some(ServerCert::load(
+                    certfile,
+                    keyfile,
+                    self.ca_certs.as_deref(),
+                )?)
+            }
+            None => None,
+        };
+
+        let mut cert_resolver = CertResolver::new(default_cert);
+        for (pattern, (certfile, keyfile)) in &self.sni_certs {
+            cert_resolver.add(pattern, ServerCert::load(certfile, keyfile, None)?)?;
+        }
+        Ok(cert_resolver)
+    }
+
+    fn client_roots(&self) -> PyResult<Option<RootCertStore>> {
+        let client_ca_certs = match &self.client_ca_certs {
+            Some(client_ca_certs) => client_ca_certs,
+            None => return Ok(None),
+        };
+
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(client_ca_certs)? {
+            roots.add(&cert).map_err(|tls_err| {
+                PyValueError::new_err(format!(
+                    "Invalid CA certificate in '{}': {}",
+                    client_ca_certs, tls_err
+                ))
+            })?;
+        }
+        Ok(Some(roots))
+    }
+
+    fn cipher_suites(&self) -> PyResult<Vec<SupportedCipherSuite>> {
+        let ciphers = match &self.ciphers {
+            Some(ciphers) => ciphers,
+            None => return Ok(ALL_CIPHER_SUITES.to_vec()),
+        };
+
+        ciphers
+            .split(':')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                ALL_CIPHER_SUITES
+                    .iter()
+                    .find(|suite| {
+                        suite
+                            .suite()
+                            .as_str()
+                            .is_some_and(|suite_name| suite_name.eq_ignore_ascii_case(name))
+                    })
+                    .copied()
+                    .ok_or_else(|| {
+                        PyValueError::new_err(format!("'{}' is not a supported cipher suite", name))
+                    })
+            })
+            .collect()
+    }
+
+    fn protocol_versions(&self) -> &'static [&'static SupportedProtocolVersion] {
+        match self.min_version {
+            TlsVersion::Tls12 => rustls::ALL_VERSIONS,
+            TlsVersion::Tls13 => TLS13_ONLY,
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            certfile: None,
+            keyfile: None,
+            ca_certs: None,
+            ciphers: None,
+            min_version: TlsVersion::Tls12,
+            client_ca_certs: None,
+            client_auth: ClientAuth::None,
+            sni_certs: BTreeMap::new(),
+            reload_interval: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+/// Terminates TLS on accepted connections.
+///
+/// Certificates can be reloaded while the server is running. Connections
+/// which are already established carry on with the certificates they were
+/// accepted with.
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    config: Arc<TlsConfig>,
+    context: Arc<RwLock<Arc<TlsContext>>>,
+}
+
+/// Everything loaded from the TLS configuration.
+struct TlsContext {
+    acceptor: tokio_rustls::TlsAcceptor,
+    cert_resolver: Arc<CertResolver>,
+}
+
+impl TlsAcceptor {
+    /// Run the TLS handshake, returning the encrypted stream and the details
+    /// of the negotiated session.
+    pub async fn accept(&self, stream: Stream) -> io::Result<(TlsStream<Stream>, TlsInfo)> {
+        let context = self.current_context();
+        let stream = context.acceptor.accept(stream).await?;
+        let session = stream.get_ref().1;
+        let server_cert = context.cert_resolver.find(session.server_name());
+        let tls_info = TlsInfo::new(server_cert, session);
+        Ok((stream, tls_info))
+    }
+
+    /// Load the certificates and keys again, for new connections to use. If
+    /// they can't be loaded, the current ones are kept.
+    pub async fn reload(&self) {
+        match self.config.clone().load_context().await {
+            Ok(context) => {
+                *self.context.write().unwrap() = Arc::new(context);
+                log::info!("Reloaded TLS certificates");
+            }
+            Err(py_err) => log::error!("Could not reload TLS certificates: {}", py_err),
+        }
+    }
+
+    /// Reload the certificates whenever the process receives SIGUSR1, or
+    /// when their files change.
+    pub async fn watch(self) {
+        #[cfg(unix)]
+        let mut reload_signal = {
+            use tokio::signal::unix::{signal, SignalKind};
+            signal(SignalKind::user_defined1()).ok()
+        };
+
+        let mut check_interval = self.config.reload_interval.map(tokio::time::interval);
+        let mut file_stamps = self.file_stamps().await;
+
+        loop {
+            #[cfg(unix)]
+            let signalled = Box::pin(async {
+                let received = match &mut reload_signal {
+                    Some(reload_signal) => reload_signal.recv().await,
+                    None => future::pending().await,
+                };
+                // The signal can't be received any more, so stop listening
+                // for it rather than reloading over and over
+                if received.is_none() {
+                    reload_signal = None;
+                }
+                received
+            });
+            #[cfg(not(unix))]
+            let signalled = Box::pin(future::pending::<Option<()>>());
+
+            let checked = Box::pin(async {
+                match &mut check_interval {
+                    Some(check_interval) => check_interval.tick().await,
+                    None => future::pending().await,
+                }
+            });
+
+            match future::select(signalled, checked).await {
+                Either::Left((Some(()), _)) => self.reload().await,
+                Either::Left((None, _)) => {}
+                Either::Right(_) => {
+                    // Files which fail to load are only retried once they
+                    // change again.
+                    let latest_file_stamps = self.file_stamps().await;
+                    if latest_file_stamps != file_stamps {
+                        file_stamps = latest_file_stamps;
+                        self.reload().await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The stamps of the certificate and key files, which are read on a
+    /// blocking thread like the files themselves.
+    async fn file_stamps(&self) -> Vec<Option<(SystemTime, u64)>> {
+        let config = self.config.clone();
+        tokio::task::spawn_blocking(move || config.file_stamps())
+            .await
+            .unwrap_or_default()
+    }
+
+    fn current_context(&self) -> Arc<TlsContext> {
+        self.context.read().unwrap().clone()
+    }
+}
+
+/// Details of an established TLS session, which are reported to the app in
+/// the ASGI `tls` extension.
+#[derive(Debug)]
+pub struct TlsInfo {
+    /// The PEM encoded certificate presented by the server.
+    pub server_cert: Option<String>,
+    /// The PEM encoded certificates presented by the client, starting with
+    /// its own certificate.
+    pub client_cert_chain: Vec<String>,
+    /// The RFC 4514 distinguished name of the client certificate.
+    pub client_cert_name: Option<String>,
+    pub tls_version: Option<u16>,
+    pub cipher_suite: Option<u16>,
+}
+
+impl TlsInfo {
+    fn new(server_cert: Option<&ServerCert>, session: &ServerConnection) -> Self {
+        let peer_certs = session.peer_certificates().unwrap_or(&[]);
+
+        TlsInfo {
+            server_cert: server_cert.map(|cert| cert.pem().to_string()),
+            client_cert_chain: peer_certs.iter().map(encode_pem).collect(),
+            client_cert_name: peer_certs
+                .first()
+                .and_then(|cert| x509_parser::parse_x509_certificate(&cert.0).ok())
+                .map(|(_, cert)| format_name(cert.subject())),
+            tls_version: session.protocol_version().map(|version| version.get_u16()),
+            cipher_suite: session
+                .negotiated_cipher_suite()
+                .map(|suite| suite.suite().get_u16()),
+        }
+    }
+}
+
+fn encode_pem(cert: &Certificate) -> String {
+    pem::encode_config(
+        &pem::Pem {
+            tag: "CERTIFICATE".to_string(),
+            contents: cert.0.clone(),
+        },
+        pem::EncodeConfig {
+            line_ending: pem::LineEnding::LF,
+        },
+    )
+}
+
+/// Format a distinguished name as a string, as specified by RFC 4514.
+fn format_name(name: &X509Name) -> String {
+    // The string representation lists the RDNs in reverse order
+    name.iter()
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .map(|rdn| rdn.iter().map(format_attribute).join("+"))
+        .join(",")
+}
+
+fn format_attribute(attribute: &AttributeTypeAndValue) -> String {
+    let attr_type = oid2sn(attribute.attr_type(), oid_registry())
+        .ok()
+        .and_then(attribute_keyword)
+        .map(str::to_string)
+        .unwrap_or_else(|| attribute.attr_type().to_id_string());
+
+    match attribute.as_str() {
+        Ok(value) => format!("{}={}", attr_type, escape_attribute_value(value)),
+        // Values which aren't strings are given as the hex of their encoding
+        Err(_) => format!(
+            "{}=#{}",
+            attr_type,
+            attribute
+                .attr_value()
+                .to_der_vec()
+                .unwrap_or_default()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .join("")
+        ),
+    }
+}
+
+/// The short names that RFC 4514 uses for common attribute types. Any others
+/// are given by their OID.
+fn attribute_keyword(name: &str) -> Option<&'static str> {
+    match name {
+        "commonName" => Some("CN"),
+        "localityName" => Some("L"),
+        "stateOrProvinceName" => Some("ST"),
+        "organizationName" => Some("O"),
+        "organizationalUnitName" => Some("OU"),
+        "countryName" => Some("C"),
+        "streetAddress" => Some("STREET"),
+        "domainComponent" => Some("DC"),
+        "userId" => Some("UID"),
+        _ => None,
+    }
+}
+
+fn escape_attribute_value(value: &str) -> String {
+    let last = value.chars().count().saturating_sub(1);
+    value
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match c {
+            '"' | '+' | ',' | ';' | '<' | '>' | '\\' => format!("\\{}", c),
+            '#' | ' ' if i == 0 => format!("\\{}", c),
+            ' ' if i == last => format!("\\{}", c),
+            '\0' => "\\00".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn open_pem_file(path: &str) -> PyResult<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|io_err| PyOSError::new_err(format!("Could not open '{}': {}", path, io_err)))
+}
+
+fn load_certs(path: &str) -> PyResult<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut open_pem_file(path)?).map_err(|io_err| {
+        PyValueError::new_err(format!("Could not read '{}': {}", path, io_err))
+    })?;
+
+    if certs.is_empty() {
+        return Err(PyValueError::new_err(format!(
+            "No certificates found in '{}'",
+            path
+        )));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_private_key(path: &str) -> PyResult<PrivateKey> {
+    let items = rustls_pemfile::read_all(&mut open_pem_file(path)?).map_err(|io_err| {
+        PyValueError::new_err(format!("Could not read '{}': {}", path, io_err))
+    })?;
+
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| PyValueError::new_err(format!("No private key found in '{}'", path)))
+}
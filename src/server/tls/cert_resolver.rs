@@ -0,0 +1,215 @@
+use std::{collections::HashMap, sync::Arc};
+
+use pyo3::{exceptions::PyValueError, PyResult};
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+};
+
+use super::{encode_pem, load_certs, load_private_key};
+
+/// A certificate chain and key which the server can present.
+pub struct ServerCert {
+    certified_key: Arc<CertifiedKey>,
+    pem: String,
+}
+
+impl ServerCert {
+    /// Load a certificate from PEM files, appending the intermediate
+    /// certificates in `ca_certs` to its chain.
+    pub fn load(certfile: &str, keyfile: &str, ca_certs: Option<&str>) -> PyResult<Self> {
+        let mut cert_chain = load_certs(certfile)?;
+        if let Some(ca_certs) = ca_certs {
+            cert_chain.extend(load_certs(ca_certs)?);
+        }
+        let key = sign::any_supported_type(&load_private_key(keyfile)?).map_err(|sign_err| {
+            PyValueError::new_err(format!("Invalid key in '{}': {}", keyfile, sign_err))
+        })?;
+
+        Ok(ServerCert {
+            pem: encode_pem(&cert_chain[0]),
+            certified_key: Arc::new(CertifiedKey::new(cert_chain, key)),
+        })
+    }
+
+    /// The PEM encoding of the certificate, without its chain.
+    pub fn pem(&self) -> &str {
+        &self.pem
+    }
+}
+
+/// Picks the certificate to present from the hostname the client asked for
+/// with SNI.
+///
+/// Hostnames are matched exactly first, then against wildcard patterns such
+/// as `*.example.com`, which match a single extra label. Clients which don't
+/// match any pattern, or don't send SNI at all, are given the default
+/// certificate if there is one, and fail the handshake otherwise.
+pub struct CertResolver {
+    default_cert: Option<ServerCert>,
+    exact_certs: HashMap<String, ServerCert>,
+    wildcard_certs: HashMap<String, ServerCert>,
+}
+
+impl CertResolver {
+    pub fn new(default_cert: Option<ServerCert>) -> Self {
+        CertResolver {
+            default_cert,
+            exact_certs: HashMap::new(),
+            wildcard_certs: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, pattern: &str, cert: ServerCert) -> PyResult<()> {
+        let pattern = normalize_hostname(pattern);
+        let (certs, hostname) = match pattern.strip_prefix("*.") {
+            Some(parent) => (&mut self.wildcard_certs, parent),
+            None => (&mut self.exact_certs, pattern.as_str()),
+        };
+
+        if hostname.is_empty() || hostname.contains('*') {
+            return Err(PyValueError::new_err(format!(
+                "'{}' is not a valid hostname pattern",
+                pattern
+            )));
+        }
+
+        certs.insert(hostname.to_string(), cert);
+        Ok(())
+    }
+
+    /// Find the certificate to present for a server name.
+    pub fn find(&self, server_name: Option<&str>) -> Option<&ServerCert> {
+        server_name
+            .map(normalize_hostname)
+            .and_then(|hostname| {
+                self.exact_certs.get(&hostname).or_else(|| {
+                    let (_, parent) = hostname.split_once('.')?;
+                    self.wildcard_certs.get(parent)
+                })
+            })
+            .or(self.default_cert.as_ref())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.find(client_hello.server_name())
+            .map(|cert| cert.certified_key.clone())
+    }
+}
+
+fn normalize_hostname(hostname: &str) -> String {
+    hostname.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::{sign::SigningKey, Certificate, SignatureAlgorithm, SignatureScheme};
+
+    use super::*;
+
+    /// Resolving only looks at hostnames, so the certificates are stand-ins
+    /// that can't sign anything, told apart by their PEM.
+    struct NoKey;
+
+    impl SigningKey for NoKey {
+        fn choose_scheme(&self, _offered: &[SignatureScheme]) -> Option<Box<dyn sign::Signer>> {
+            None
+        }
+
+        fn algorithm(&self) -> SignatureAlgorithm {
+            SignatureAlgorithm::ED25519
+        }
+    }
+
+    fn cert(name: &str) -> ServerCert {
+        ServerCert {
+            certified_key: Arc::new(CertifiedKey::new(
+                vec![Certificate(name.as_bytes().to_vec())],
+                Arc::new(NoKey),
+            )),
+            pem: name.to_string(),
+        }
+    }
+
+    fn resolver(default_cert: Option<&str>, patterns: &[&str]) -> CertResolver {
+        let mut resolver = CertResolver::new(default_cert.map(cert));
+        for pattern in patterns {
+            resolver.add(pattern, cert(pattern)).unwrap();
+        }
+        resolver
+    }
+
+    fn found<'a>(resolver: &'a CertResolver, server_name: Option<&str>) -> Option<&'a str> {
+        resolver.find(server_name).map(ServerCert::pem)
+    }
+
+    #[test]
+    fn exact_names_beat_wildcards() {
+        let resolver = resolver(None, &["*.example.com", "api.example.com"]);
+        assert_eq!(
+            found(&resolver, Some("api.example.com")),
+            Some("api.example.com")
+        );
+        assert_eq!(
+            found(&resolver, Some("www.example.com")),
+            Some("*.example.com")
+        );
+    }
+
+    #[test]
+    fn wildcards_match_exactly_one_label() {
+        let resolver = resolver(None, &["*.example.com"]);
+        assert_eq!(found(&resolver, Some("example.com")), None);
+        assert_eq!(found(&resolver, Some("a.b.example.com")), None);
+        assert_eq!(found(&resolver, Some("a.example.org")), None);
+    }
+
+    #[test]
+    fn names_are_matched_without_case_or_a_trailing_dot() {
+        let resolver = resolver(None, &["API.Example.com.", "*.Example.org"]);
+        assert_eq!(
+            found(&resolver, Some("api.example.COM.")),
+            Some("API.Example.com.")
+        );
+        assert_eq!(
+            found(&resolver, Some("WWW.example.org")),
+            Some("*.Example.org")
+        );
+    }
+
+    #[test]
+    fn clients_without_sni_get_the_default() {
+        let resolver = resolver(Some("default"), &["api.example.com"]);
+        assert_eq!(found(&resolver, None), Some("default"));
+        assert_eq!(found(&resolver, Some("other.example.com")), Some("default"));
+    }
+
+    #[test]
+    fn unknown_names_without_a_default_get_nothing() {
+        let resolver = resolver(None, &["api.example.com"]);
+        assert_eq!(found(&resolver, Some("other.example.com")), None);
+        assert_eq!(found(&resolver, None), None);
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let mut resolver = CertResolver::new(None);
+        for pattern in &[
+            "",
+            ".",
+            "*",
+            "*.",
+            "*.*.example.com",
+            "a.*.example.com",
+            "w*.example.com",
+        ] {
+            assert!(
+                resolver.add(pattern, cert(pattern)).is_err(),
+                "{:?} should be rejected",
+                pattern
+            );
+        }
+    }
+}
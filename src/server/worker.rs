@@ -0,0 +1,177 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// The environment variable giving a worker the file descriptor of its
+/// link to the supervisor, which the worker's Python entry point passes on
+/// as the `supervisor_link_fd` setting.
+pub const LINK_FD_ENV: &str = "ASGI_SERVER_LINK_FD";
+
+/// Sent by a worker once it is serving connections.
+pub const READY: u8 = b'R';
+/// Sent by a worker that should be replaced.
+pub const RETIRE: u8 = b'X';
+
+/// When a worker asks to be replaced.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorkerConfig {
+    pub max_requests: Option<u64>,
+    /// Up to this many requests are added to `max_requests` at random, so
+    /// that workers started together don't all retire together.
+    pub max_requests_jitter: u64,
+    /// The descriptor of the link to the supervisor that started this
+    /// process, if any.
+    pub supervisor_link_fd: Option<i32>,
+}
+
+impl WorkerConfig {
+    fn request_limit(&self) -> Option<u64> {
+        let max_requests = self.max_requests?;
+        let jitter = match self.max_requests_jitter {
+            0 => 0,
+            max_jitter => RandomState::new().build_hasher().finish() % (max_jitter + 1),
+        };
+        Some(max_requests.saturating_add(jitter))
+    }
+}
+
+/// A worker's link to the supervisor that started it, which it tells when
+/// it is ready to serve, and when it should be replaced.
+pub struct WorkerLink {
+    #[cfg(unix)]
+    stream: std::os::unix::net::UnixStream,
+    request_limit: Option<u64>,
+    requests: AtomicU64,
+}
+
+impl WorkerLink {
+    /// The link to this process's supervisor, if it was started by one.
+    #[cfg(unix)]
+    pub fn from_config(config: &WorkerConfig) -> Option<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        let fd = config.supervisor_link_fd?;
+        // SAFETY: the supervisor passes the link at this descriptor, which
+        // nothing else in the process owns
+        let stream = unsafe {
+            // Subprocesses of the app shouldn't inherit the link
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            std::os::unix::net::UnixStream::from_raw_fd(fd)
+        };
+
+        Some(WorkerLink {
+            stream,
+            request_limit: config.request_limit(),
+            requests: AtomicU64::new(0),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_config(_config: &WorkerConfig) -> Option<Self> {
+        None
+    }
+
+    pub fn ready(&self) {
+        self.send(READY);
+    }
+
+    /// Count a request, and ask to be replaced once the worker has served
+    /// as many as it should.
+    pub fn request_received(&self) {
+        let requests = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        if Some(requests) == self.request_limit {
+            log::info!("Served {} requests; asking to be replaced", requests);
+            self.send(RETIRE);
+        }
+    }
+
+    /// Wait until the supervisor's end of the link closes, which happens
+    /// when it exits for any reason, including being killed.
+    #[cfg(unix)]
+    pub async fn supervisor_exited(&self) -> io::Result<()> {
+        use tokio::io::AsyncReadExt;
+
+        // This makes the writes in `send` non-blocking too, which is fine as
+        // the supervisor reads each byte as soon as it is sent
+        let stream = self.stream.try_clone()?;
+        stream.set_nonblocking(true)?;
+        let mut stream = tokio::net::UnixStream::from_std(stream)?;
+        // The supervisor never writes to the link
+        while stream.read(&mut [0u8]).await? > 0 {}
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub async fn supervisor_exited(&self) -> io::Result<()> {
+        futures::future::pending().await
+    }
+
+    #[cfg(unix)]
+    fn send(&self, message: u8) {
+        use std::io::Write;
+
+        if let Err(io_err) = (&self.stream).write_all(&[message]) {
+            log::warn!("Could not message the supervisor: {}", io_err);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _message: u8) {}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        os::unix::{
+            io::{AsRawFd, IntoRawFd},
+            net::UnixStream,
+            process::CommandExt,
+        },
+        process::Command,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn notices_when_the_supervisor_is_killed() {
+        let (supervisor_end, worker_end) = UnixStream::pair().unwrap();
+        let supervisor_fd = supervisor_end.as_raw_fd();
+        // A stand-in for the supervisor, which holds its end of the link
+        let mut supervisor = Command::new("sleep");
+        supervisor.arg("60");
+        // SAFETY: only async-signal-safe functions are called between fork
+        // and exec
+        unsafe {
+            supervisor.pre_exec(move || {
+                if libc::dup2(supervisor_fd, 3) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut supervisor = supervisor.spawn().unwrap();
+        drop(supervisor_end);
+
+        let link = WorkerLink::from_config(&WorkerConfig {
+            supervisor_link_fd: Some(worker_end.into_raw_fd()),
+            ..WorkerConfig::default()
+        })
+        .unwrap();
+        let supervisor_exited = link.supervisor_exited();
+        tokio::pin!(supervisor_exited);
+        let still_running =
+            tokio::time::timeout(Duration::from_millis(50), &mut supervisor_exited).await;
+        assert!(still_running.is_err());
+
+        supervisor.kill().unwrap();
+        supervisor.wait().unwrap();
+        tokio::time::timeout(Duration::from_secs(5), supervisor_exited)
+            .await
+            .expect("the worker should notice that the supervisor has exited")
+            .unwrap();
+    }
+}
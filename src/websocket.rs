@@ -0,0 +1,464 @@
+mod accept_message;
+mod close_message;
+mod connect_message;
+mod disconnect_message;
+mod receive_message;
+mod scope;
+mod send_message;
+
+use std::{
+    convert::{Infallible, TryFrom},
+    time::Duration,
+};
+
+use futures::{
+    channel::mpsc,
+    future,
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use http::{
+    header::{
+        HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION,
+        UPGRADE,
+    },
+    response, Request, Response, StatusCode,
+};
+use hyper::{upgrade::Upgraded, Body};
+use pyo3::{exceptions::PyValueError, types::PyDict, Py, PyErr, PyResult, Python};
+use tokio::sync::OwnedSemaphorePermit;
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::derive_accept_key,
+        protocol::{frame::coding::CloseCode, CloseFrame, Role},
+        Message,
+    },
+    WebSocketStream,
+};
+
+use self::{
+    accept_message::WebsocketAccept, close_message::WebsocketClose,
+    connect_message::WebsocketConnect, disconnect_message::WebsocketDisconnect,
+    receive_message::WebsocketReceive, send_message::WebsocketSend,
+};
+use crate::http::{call_asgi_app, handle_error, HttpResponseBody};
+use crate::{
+    asgi_driver::AsgiDriver,
+    asgi_message::AsgiMessage,
+    error,
+    helpers::into_py_message,
+    server::{ConnectionInfo, ConnectionTasks},
+};
+
+const WEBSOCKET_VERSION: &str = "13";
+
+/// How long the client has to finish the closing handshake once the app
+/// has closed the socket.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Check whether a request asks for its connection to be upgraded to the
+/// WebSocket protocol.
+pub fn is_websocket_request(request: &Request<Body>) -> bool {
+    let headers = request.headers();
+    header_contains_token(headers.get_all(CONNECTION).iter(), "upgrade")
+        && header_contains_token(headers.get_all(UPGRADE).iter(), "websocket")
+}
+
+/// Run the ASGI WebSocket protocol for an upgrade request.
+///
+/// The app is sent `websocket.connect`, and the handshake is completed or
+/// rejected depending on whether it replies with `websocket.accept` or
+/// `websocket.close`, or returns without replying. Once accepted, frames
+/// are relayed between the client and the app until either side closes the
+/// socket. The session runs as one of the server's connection tasks, as
+/// the connection did before it was upgraded, and holds on to the request's
+/// place under the concurrent request limit until it ends.
+pub async fn handle_request(
+    connection: ConnectionInfo,
+    mut request: Request<Body>,
+    asgi_driver: AsgiDriver,
+    connection_tasks: ConnectionTasks,
+    permit: Option<OwnedSemaphorePermit>,
+) -> Result<Response<HttpResponseBody>, Infallible> {
+    let on_upgrade = hyper::upgrade::on(&mut request);
+    let (parts, _) = request.into_parts();
+    let req_path = parts.uri.path().to_string();
+
+    let accept_key = match get_accept_key(&parts.headers) {
+        Some(accept_key) => accept_key,
+        None => return Ok(bad_request_response()),
+    };
+
+    // These channels will be used to communicate between python and rust
+    let (results_from_py, mut messages_to_rust) = mpsc::channel::<Py<PyDict>>(1);
+    let (mut results_from_rust, messages_to_py) = mpsc::channel::<Py<PyDict>>(1);
+
+    let rt = pyo3_asyncio::tokio::get_runtime();
+
+    rt.spawn(call_asgi_app(
+        scope::build(parts, &connection, asgi_driver.state()),
+        asgi_driver,
+        messages_to_py,
+        results_from_py,
+    ));
+
+    results_from_rust
+        .send(into_py_message(WebsocketConnect))
+        .await
+        .unwrap_or(());
+
+    // An app that returns without accepting has rejected the connection
+    let handshake = match messages_to_rust.next().await {
+        Some(message_dict) => Handshake::try_from(message_dict),
+        None => Ok(Handshake::Close),
+    };
+
+    let response = handshake.and_then(|handshake| match handshake {
+        Handshake::Accept(accept_message) => {
+            connection_tasks.spawn(async move {
+                let _permit = permit;
+                match on_upgrade.await {
+                    Ok(upgraded) => {
+                        run_websocket(upgraded, messages_to_rust, results_from_rust).await
+                    }
+                    Err(hyper_err) => {
+                        error::ServerError::from(hyper_err).handle(()).unwrap();
+                        results_from_rust
+                            .send(into_py_message(WebsocketDisconnect::abnormal()))
+                            .await
+                            .unwrap_or(());
+                    }
+                }
+            });
+            build_response(response::Builder::from(accept_message).header(
+                SEC_WEBSOCKET_ACCEPT,
+                HeaderValue::from_str(&accept_key).unwrap(),
+            ))
+        }
+        Handshake::Close => {
+            connection_tasks.spawn(async move {
+                results_from_rust
+                    .send(into_py_message(WebsocketDisconnect::abnormal()))
+                    .await
+                    .unwrap_or(());
+            });
+            Ok(forbidden_response())
+        }
+    });
+
+    if let Ok(response) = &response {
+        log::info!(
+            "{} - \"WebSocket {}\" {}",
+            connection.remote_addr(),
+            req_path,
+            response.status().as_u16()
+        );
+    }
+
+    response.or_else(handle_error)
+}
+
+/// The app's reply to `websocket.connect`.
+enum Handshake {
+    Accept(WebsocketAccept),
+    Close,
+}
+
+impl TryFrom<Py<PyDict>> for Handshake {
+    type Error = PyErr;
+
+    fn try_from(message_dict: Py<PyDict>) -> Result<Self, Self::Error> {
+        Python::with_gil(|py| {
+            let message_dict = message_dict.as_ref(py);
+            if WebsocketClose::matches_message_type(message_dict)? {
+                WebsocketClose::try_from(message_dict).map(|_| Handshake::Close)
+            } else {
+                WebsocketAccept::try_from(message_dict).map(Handshake::Accept)
+            }
+        })
+    }
+}
+
+/// A message sent by the app on an accepted socket.
+enum WebsocketEvent {
+    Send(WebsocketSend),
+    Close(WebsocketClose),
+}
+
+impl TryFrom<Py<PyDict>> for WebsocketEvent {
+    type Error = PyErr;
+
+    fn try_from(message_dict: Py<PyDict>) -> Result<Self, Self::Error> {
+        Python::with_gil(|py| {
+            let message_dict = message_dict.as_ref(py);
+            if WebsocketClose::matches_message_type(message_dict)? {
+                WebsocketClose::try_from(message_dict).map(WebsocketEvent::Close)
+            } else {
+                WebsocketSend::try_from(message_dict).map(WebsocketEvent::Send)
+            }
+        })
+    }
+}
+
+async fn run_websocket(
+    upgraded: Upgraded,
+    messages_to_rust: mpsc::Receiver<Py<PyDict>>,
+    results_from_rust: mpsc::Sender<Py<PyDict>>,
+) {
+    let websocket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+    let (websocket_sink, websocket_stream) = websocket.split();
+
+    // If the client goes away first, the app has been told so and there's
+    // nothing left to send to it. If the app is done first, the client's
+    // reply to its close frame is still waited for, so that the closing
+    // handshake completes and the app gets `websocket.disconnect`.
+    let to_py = Box::pin(forward_to_py(websocket_stream, results_from_rust));
+    let to_client = Box::pin(forward_to_client(messages_to_rust, websocket_sink));
+    if let future::Either::Right((_, to_py)) = future::select(to_py, to_client).await {
+        tokio::time::timeout(CLOSE_TIMEOUT, to_py)
+            .await
+            .unwrap_or(());
+    }
+}
+
+/// Relay the client's messages to the app until the client closes the
+/// socket or goes away, and then tell the app so. Once the app stops
+/// listening, the client's messages are read and dropped.
+async fn forward_to_py(
+    mut websocket_stream: SplitStream<WebSocketStream<Upgraded>>,
+    mut results_from_rust: mpsc::Sender<Py<PyDict>>,
+) {
+    let disconnect = loop {
+        let message = match websocket_stream.next().await {
+            Some(Ok(Message::Text(text))) => WebsocketReceive::Text(text),
+            Some(Ok(Message::Binary(data))) => WebsocketReceive::Bytes(data),
+            Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => continue,
+            Some(Ok(Message::Close(close_frame))) => {
+                // keep polling so that the closing handshake reply is flushed
+                while let Some(Ok(_)) = websocket_stream.next().await {}
+                break WebsocketDisconnect::from(close_frame);
+            }
+            Some(Err(_)) | None => break WebsocketDisconnect::abnormal(),
+        };
+
+        if !results_from_rust.is_closed() {
+            results_from_rust
+                .send(into_py_message(message))
+                .await
+                .unwrap_or(());
+        }
+    };
+
+    results_from_rust
+        .send(into_py_message(disconnect))
+        .await
+        .unwrap_or(());
+}
+
+async fn forward_to_client(
+    mut messages_to_rust: mpsc::Receiver<Py<PyDict>>,
+    mut websocket_sink: SplitSink<WebSocketStream<Upgraded>, Message>,
+) {
+    while let Some(message_dict) = messages_to_rust.next().await {
+        match WebsocketEvent::try_from(message_dict) {
+            Ok(WebsocketEvent::Send(send_message)) => {
+                if websocket_sink.send(send_message.into()).await.is_err() {
+                    break;
+                }
+            }
+            Ok(WebsocketEvent::Close(close_message)) => {
+                websocket_sink
+                    .send(close_message.into())
+                    .await
+                    .unwrap_or(());
+                break;
+            }
+            Err(py_err) => {
+                error::ApplicationError::from(py_err).handle(()).unwrap();
+                let close_frame = CloseFrame {
+                    code: CloseCode::Error,
+                    reason: "".into(),
+                };
+                websocket_sink
+                    .send(Message::Close(Some(close_frame)))
+                    .await
+                    .unwrap_or(());
+                break;
+            }
+        }
+    }
+
+    websocket_sink.close().await.unwrap_or(());
+}
+
+fn get_accept_key(headers: &http::HeaderMap) -> Option<String> {
+    let version = headers.get(SEC_WEBSOCKET_VERSION)?;
+    if version != WEBSOCKET_VERSION {
+        return None;
+    }
+
+    let key = headers.get(SEC_WEBSOCKET_KEY)?;
+    Some(derive_accept_key(key.as_bytes()))
+}
+
+fn header_contains_token<'a>(
+    mut header_values: impl Iterator<Item = &'a HeaderValue>,
+    token: &str,
+) -> bool {
+    header_values.any(|header_value| {
+        header_value
+            .to_str()
+            .map(|header_value| {
+                header_value
+                    .split(',')
+                    .any(|item| item.trim().eq_ignore_ascii_case(token))
+            })
+            .unwrap_or(false)
+    })
+}
+
+fn build_response(builder: response::Builder) -> PyResult<Response<HttpResponseBody>> {
+    builder
+        .body(HttpResponseBody::new())
+        .map_err(|err| PyValueError::new_err(format!("Could not complete handshake: {}", err)))
+}
+
+fn bad_request_response() -> Response<HttpResponseBody> {
+    response::Builder::new()
+        .status(StatusCode::BAD_REQUEST)
+        .header(SEC_WEBSOCKET_VERSION, WEBSOCKET_VERSION)
+        .body(HttpResponseBody::new())
+        .unwrap()
+}
+
+/// The response to a handshake that the app rejected.
+fn forbidden_response() -> Response<HttpResponseBody> {
+    response::Builder::new()
+        .status(StatusCode::FORBIDDEN)
+        .body(HttpResponseBody::new())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use http::header::SEC_WEBSOCKET_PROTOCOL;
+    use pyo3::types::IntoPyDict;
+
+    use super::*;
+    use crate::server::Address;
+
+    /// The key and accept value from the example in RFC 6455, section 1.3.
+    const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+    const ACCEPT: &str = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+
+    fn upgrade_request(connection: &str, upgrade: &str) -> Request<Body> {
+        Request::get("/socket")
+            .header(CONNECTION, connection)
+            .header(UPGRADE, upgrade)
+            .header(SEC_WEBSOCKET_VERSION, WEBSOCKET_VERSION)
+            .header(SEC_WEBSOCKET_KEY, KEY)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn message(entries: &[(&str, &str)]) -> Py<PyDict> {
+        Python::with_gil(|py| entries.into_py_dict(py).into())
+    }
+
+    #[test]
+    fn recognizes_upgrade_requests() {
+        assert!(is_websocket_request(&upgrade_request(
+            "Upgrade",
+            "websocket"
+        )));
+        assert!(is_websocket_request(&upgrade_request(
+            "keep-alive, upgrade",
+            "WebSocket"
+        )));
+        assert!(!is_websocket_request(&upgrade_request(
+            "keep-alive",
+            "websocket"
+        )));
+        assert!(!is_websocket_request(&upgrade_request("Upgrade", "h2c")));
+        assert!(!is_websocket_request(
+            &Request::get("/").body(Body::empty()).unwrap()
+        ));
+    }
+
+    #[test]
+    fn header_tokens_are_whole_items_of_any_value() {
+        let values = [
+            HeaderValue::from_static("keep-alive"),
+            HeaderValue::from_static("foo, Upgrade"),
+        ];
+        assert!(header_contains_token(values.iter(), "upgrade"));
+        assert!(!header_contains_token(values.iter(), "alive"));
+        let upgraded = [HeaderValue::from_static("upgraded")];
+        assert!(!header_contains_token(upgraded.iter(), "upgrade"));
+        assert!(!header_contains_token([].iter(), "upgrade"));
+    }
+
+    #[test]
+    fn derives_the_accept_key() {
+        let request = upgrade_request("Upgrade", "websocket");
+        assert_eq!(get_accept_key(request.headers()).as_deref(), Some(ACCEPT));
+    }
+
+    #[test]
+    fn needs_a_key_and_version_13() {
+        let mut request = upgrade_request("Upgrade", "websocket");
+        request
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("8"));
+        assert_eq!(get_accept_key(request.headers()), None);
+
+        let mut request = upgrade_request("Upgrade", "websocket");
+        request.headers_mut().remove(SEC_WEBSOCKET_KEY);
+        assert_eq!(get_accept_key(request.headers()), None);
+    }
+
+    #[tokio::test]
+    async fn wrong_version_is_a_bad_request() {
+        let mut request = upgrade_request("Upgrade", "websocket");
+        request
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("8"));
+        let address = Address::Inet("127.0.0.1:8000".parse::<SocketAddr>().unwrap());
+        let connection = ConnectionInfo::new(address.clone(), address, None);
+        let driver = AsgiDriver::new(Python::with_gil(|py| py.None()));
+
+        let response = handle_request(connection, request, driver, ConnectionTasks::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[SEC_WEBSOCKET_VERSION], WEBSOCKET_VERSION);
+    }
+
+    #[test]
+    fn app_accepts_the_handshake() {
+        let accept = message(&[("type", "websocket.accept"), ("subprotocol", "chat")]);
+        match Handshake::try_from(accept).unwrap() {
+            Handshake::Accept(accept_message) => {
+                let response = build_response(response::Builder::from(accept_message)).unwrap();
+                assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+                assert_eq!(response.headers()[SEC_WEBSOCKET_PROTOCOL], "chat");
+            }
+            Handshake::Close => panic!("the handshake should be accepted"),
+        }
+    }
+
+    #[test]
+    fn app_closing_the_handshake_rejects_it_with_403() {
+        let close = message(&[("type", "websocket.close")]);
+        assert!(matches!(Handshake::try_from(close), Ok(Handshake::Close)));
+        assert_eq!(forbidden_response().status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn other_replies_to_the_handshake_are_errors() {
+        let send = message(&[("type", "websocket.send"), ("text", "hi")]);
+        assert!(Handshake::try_from(send).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use std::convert::TryFrom;
+
+use http::{
+    header::{CONNECTION, SEC_WEBSOCKET_PROTOCOL, UPGRADE},
+    response, HeaderMap, HeaderValue, StatusCode,
+};
+use pyo3::{exceptions::PyValueError, types::PyDict, PyErr, PyResult};
+
+use crate::asgi_message::AsgiMessage;
+use crate::http::headers;
+
+pub struct WebsocketAccept {
+    subprotocol: Option<HeaderValue>,
+    headers: HeaderMap,
+}
+
+impl WebsocketAccept {
+    fn get_subprotocol(message_dict: &PyDict) -> PyResult<Option<HeaderValue>> {
+        match message_dict.get_item("subprotocol") {
+            Some(subprotocol) if !subprotocol.is_none() => {
+                let subprotocol = subprotocol.extract::<&str>()?;
+                HeaderValue::from_str(subprotocol)
+                    .map(Some)
+                    .map_err(|invalid_err| {
+                        PyValueError::new_err(format!("Invalid subprotocol: '{}'", invalid_err))
+                    })
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn get_headers(message_dict: &PyDict) -> PyResult<HeaderMap> {
+        match message_dict.get_item("headers") {
+            Some(py_headers) => headers::get_headers(py_headers),
+            None => Ok(HeaderMap::new()),
+        }
+    }
+}
+
+impl AsgiMessage for WebsocketAccept {
+    fn message_type() -> &'static str {
+        "websocket.accept"
+    }
+}
+
+impl TryFrom<&PyDict> for WebsocketAccept {
+    type Error = PyErr;
+
+    fn try_from(message_dict: &PyDict) -> Result<Self, Self::Error> {
+        WebsocketAccept::validate_message_type(message_dict)?;
+        let subprotocol = WebsocketAccept::get_subprotocol(message_dict)?;
+        let headers = WebsocketAccept::get_headers(message_dict)?;
+        Ok(WebsocketAccept {
+            subprotocol,
+            headers,
+        })
+    }
+}
+
+impl From<WebsocketAccept> for response::Builder {
+    fn from(accept_message: WebsocketAccept) -> Self {
+        let WebsocketAccept {
+            subprotocol,
+            headers,
+        } = accept_message;
+
+        let mut builder = response::Builder::new();
+        if let Some(header_map) = builder.headers_mut() {
+            header_map.extend(headers);
+            header_map.insert(UPGRADE, HeaderValue::from_static("websocket"));
+            header_map.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+            if let Some(subprotocol) = subprotocol {
+                header_map.insert(SEC_WEBSOCKET_PROTOCOL, subprotocol);
+            }
+        }
+        builder.status(StatusCode::SWITCHING_PROTOCOLS)
+    }
+}
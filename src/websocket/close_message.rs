@@ -0,0 +1,57 @@
+use std::convert::TryFrom;
+
+use pyo3::{types::PyDict, PyErr, PyResult};
+use tokio_tungstenite::tungstenite::{
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Message,
+};
+
+use crate::asgi_message::AsgiMessage;
+
+pub struct WebsocketClose {
+    code: u16,
+    reason: String,
+}
+
+impl WebsocketClose {
+    fn get_code(message_dict: &PyDict) -> PyResult<u16> {
+        match message_dict.get_item("code") {
+            Some(code) if !code.is_none() => code.extract::<u16>(),
+            _ => Ok(CloseCode::Normal.into()),
+        }
+    }
+
+    fn get_reason(message_dict: &PyDict) -> PyResult<String> {
+        match message_dict.get_item("reason") {
+            Some(reason) if !reason.is_none() => reason.extract::<String>(),
+            _ => Ok(String::new()),
+        }
+    }
+}
+
+impl AsgiMessage for WebsocketClose {
+    fn message_type() -> &'static str {
+        "websocket.close"
+    }
+}
+
+impl TryFrom<&PyDict> for WebsocketClose {
+    type Error = PyErr;
+
+    fn try_from(message_dict: &PyDict) -> Result<Self, Self::Error> {
+        WebsocketClose::validate_message_type(message_dict)?;
+        let code = WebsocketClose::get_code(message_dict)?;
+        let reason = WebsocketClose::get_reason(message_dict)?;
+        Ok(WebsocketClose { code, reason })
+    }
+}
+
+impl From<WebsocketClose> for Message {
+    fn from(close_message: WebsocketClose) -> Self {
+        let WebsocketClose { code, reason } = close_message;
+        Message::Close(Some(CloseFrame {
+            code: CloseCode::from(code),
+            reason: reason.into(),
+        }))
+    }
+}
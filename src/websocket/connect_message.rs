@@ -0,0 +1,24 @@
+use pyo3::{
+    types::{IntoPyDict, PyDict},
+    Python,
+};
+
+use crate::asgi_message::AsgiMessage;
+
+pub struct WebsocketConnect;
+
+impl AsgiMessage for WebsocketConnect {
+    fn message_type() -> &'static str {
+        "websocket.connect"
+    }
+}
+
+impl IntoPyDict for WebsocketConnect {
+    fn into_py_dict(self, py: Python<'_>) -> &PyDict {
+        let dict = PyDict::new(py);
+
+        dict.set_item("type", Self::message_type()).unwrap_or(());
+
+        dict
+    }
+}
@@ -0,0 +1,88 @@
+use pyo3::{
+    types::{IntoPyDict, PyDict},
+    Python,
+};
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+
+use crate::asgi_message::AsgiMessage;
+
+pub struct WebsocketDisconnect {
+    code: u16,
+}
+
+impl WebsocketDisconnect {
+    /// The connection was dropped without a closing handshake.
+    pub fn abnormal() -> Self {
+        WebsocketDisconnect {
+            code: CloseCode::Abnormal.into(),
+        }
+    }
+}
+
+impl From<Option<CloseFrame<'_>>> for WebsocketDisconnect {
+    fn from(close_frame: Option<CloseFrame>) -> Self {
+        let code = close_frame
+            .map(|frame| frame.code)
+            .unwrap_or(CloseCode::Status);
+        WebsocketDisconnect { code: code.into() }
+    }
+}
+
+impl AsgiMessage for WebsocketDisconnect {
+    fn message_type() -> &'static str {
+        "websocket.disconnect"
+    }
+}
+
+impl IntoPyDict for WebsocketDisconnect {
+    fn into_py_dict(self, py: Python<'_>) -> &PyDict {
+        let dict = PyDict::new(py);
+
+        dict.set_item("type", Self::message_type()).unwrap_or(());
+        dict.set_item("code", self.code).unwrap_or(());
+
+        dict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{types::IntoPyDict, Python};
+
+    use super::*;
+
+    fn code(disconnect: WebsocketDisconnect) -> u16 {
+        Python::with_gil(|py| {
+            let dict = disconnect.into_py_dict(py);
+            assert!(WebsocketDisconnect::matches_message_type(dict).unwrap());
+            dict.get_item("code").unwrap().extract().unwrap()
+        })
+    }
+
+    fn close_frame(code: CloseCode) -> Option<CloseFrame<'static>> {
+        Some(CloseFrame {
+            code,
+            reason: "".into(),
+        })
+    }
+
+    #[test]
+    fn has_the_code_the_client_closed_with() {
+        let disconnect = WebsocketDisconnect::from(close_frame(CloseCode::Normal));
+        assert_eq!(code(disconnect), 1000);
+        let disconnect = WebsocketDisconnect::from(close_frame(CloseCode::Away));
+        assert_eq!(code(disconnect), 1001);
+        let disconnect = WebsocketDisconnect::from(close_frame(CloseCode::from(4001)));
+        assert_eq!(code(disconnect), 4001);
+    }
+
+    #[test]
+    fn close_frame_without_a_code_is_1005() {
+        assert_eq!(code(WebsocketDisconnect::from(None)), 1005);
+    }
+
+    #[test]
+    fn connection_dropped_without_closing_is_1006() {
+        assert_eq!(code(WebsocketDisconnect::abnormal()), 1006);
+    }
+}
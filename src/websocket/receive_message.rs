@@ -0,0 +1,33 @@
+use pyo3::{
+    types::{IntoPyDict, PyBytes, PyDict},
+    Python,
+};
+
+use crate::asgi_message::AsgiMessage;
+
+pub enum WebsocketReceive {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl AsgiMessage for WebsocketReceive {
+    fn message_type() -> &'static str {
+        "websocket.receive"
+    }
+}
+
+impl IntoPyDict for WebsocketReceive {
+    fn into_py_dict(self, py: Python<'_>) -> &PyDict {
+        let dict = PyDict::new(py);
+
+        dict.set_item("type", Self::message_type()).unwrap_or(());
+        match self {
+            WebsocketReceive::Text(text) => dict.set_item("text", text).unwrap_or(()),
+            WebsocketReceive::Bytes(data) => dict
+                .set_item("bytes", PyBytes::new(py, &data))
+                .unwrap_or(()),
+        }
+
+        dict
+    }
+}
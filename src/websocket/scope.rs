@@ -0,0 +1,42 @@
+mod providers;
+
+use pyo3::{types::PyDict, Py};
+
+use crate::http::scope::{providers as http_providers, HttpScopeBuilder};
+use crate::{asgi_scope, server::ConnectionInfo};
+
+pub fn build(
+    parts: http::request::Parts,
+    connection: &ConnectionInfo,
+    state: Py<PyDict>,
+) -> impl asgi_scope::ScopeProvider {
+    asgi_scope::ScopeBuilder::new()
+        .add_provider(asgi_scope::providers::Type::WebSocket)
+        .add_provider(asgi_scope::providers::AsgiVersion {
+            spec_version: "2.3",
+        })
+        .add_provider(http_providers::HttpAddress::ClientSocket(
+            connection.remote_addr().clone(),
+        ))
+        .add_provider(http_providers::HttpAddress::server(connection))
+        .add_provider(asgi_scope::providers::Extensions)
+        .add_provider(http_providers::TlsExtension(connection.tls()))
+        .add_provider(http_providers::ProxyProtocolExtension(
+            connection.proxy_header(),
+        ))
+        .add_provider(asgi_scope::providers::State::Connection(state))
+        .add_provider(
+            HttpScopeBuilder::new(parts)
+                .add_provider(http_providers::HttpVersion {})
+                .add_provider(providers::WebsocketScheme {
+                    secure: connection.is_secure(),
+                })
+                .add_provider(http_providers::HttpPath {
+                    root_path: connection.root_path().to_string(),
+                    root_path_included: connection.root_path_included(),
+                })
+                .add_provider(http_providers::HttpQueryString {})
+                .add_provider(http_providers::HttpHeaders {})
+                .add_provider(providers::WebsocketSubprotocols {}),
+        )
+}
@@ -0,0 +1,42 @@
+use http::header::SEC_WEBSOCKET_PROTOCOL;
+use pyo3::{
+    types::{PyDict, PyList},
+    PyResult, Python,
+};
+
+use crate::http::scope::HttpScopeProvider;
+
+pub struct WebsocketScheme {
+    pub secure: bool,
+}
+impl HttpScopeProvider for WebsocketScheme {
+    fn add_scope(&self, parts: &http::request::Parts, scope_dict: &PyDict) -> PyResult<()> {
+        let scheme = match parts.uri.scheme_str() {
+            _ if self.secure => "wss",
+            Some("https") | Some("wss") => "wss",
+            _ => "ws",
+        };
+        scope_dict.set_item("scheme", scheme)
+    }
+}
+
+pub struct WebsocketSubprotocols;
+impl HttpScopeProvider for WebsocketSubprotocols {
+    fn add_scope(&self, parts: &http::request::Parts, scope_dict: &PyDict) -> PyResult<()> {
+        Python::with_gil(|py| {
+            let py_subprotocols = PyList::empty(py);
+            let subprotocols = parts
+                .headers
+                .get_all(SEC_WEBSOCKET_PROTOCOL)
+                .iter()
+                .filter_map(|header_value| header_value.to_str().ok())
+                .flat_map(|header_value| header_value.split(','))
+                .map(str::trim)
+                .filter(|subprotocol| !subprotocol.is_empty());
+            for subprotocol in subprotocols {
+                py_subprotocols.append(subprotocol)?;
+            }
+            scope_dict.set_item("subprotocols", py_subprotocols)
+        })
+    }
+}
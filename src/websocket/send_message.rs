@@ -0,0 +1,60 @@
+use std::convert::TryFrom;
+
+use pyo3::{
+    exceptions::PyValueError,
+    types::{PyBytes, PyDict, PyUnicode},
+    PyAny, PyErr, PyResult,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::asgi_message::AsgiMessage;
+
+pub struct WebsocketSend(Message);
+
+impl WebsocketSend {
+    fn get_optional_item<'a>(message_dict: &'a PyDict, key: &str) -> Option<&'a PyAny> {
+        message_dict.get_item(key).filter(|item| !item.is_none())
+    }
+
+    fn get_message(message_dict: &PyDict) -> PyResult<Message> {
+        let bytes = WebsocketSend::get_optional_item(message_dict, "bytes");
+        let text = WebsocketSend::get_optional_item(message_dict, "text");
+
+        match (bytes, text) {
+            (Some(bytes), None) => {
+                let py_bytes = bytes.downcast::<PyBytes>().map_err(PyErr::from)?;
+                Ok(Message::Binary(py_bytes.as_bytes().to_vec()))
+            }
+            (None, Some(text)) => {
+                let py_text = text.downcast::<PyUnicode>().map_err(PyErr::from)?;
+                Ok(Message::Text(py_text.to_str()?.to_string()))
+            }
+            _ => Err(PyValueError::new_err(
+                "Exactly one of 'bytes' or 'text' must be provided in 'websocket.send'",
+            )),
+        }
+    }
+}
+
+impl AsgiMessage for WebsocketSend {
+    fn message_type() -> &'static str {
+        "websocket.send"
+    }
+}
+
+impl TryFrom<&PyDict> for WebsocketSend {
+    type Error = PyErr;
+
+    fn try_from(message_dict: &PyDict) -> Result<Self, Self::Error> {
+        WebsocketSend::validate_message_type(message_dict)
+            .and_then(|_| WebsocketSend::get_message(message_dict))
+            .map(WebsocketSend)
+    }
+}
+
+impl From<WebsocketSend> for Message {
+    fn from(send_message: WebsocketSend) -> Self {
+        let WebsocketSend(message) = send_message;
+        message
+    }
+}